@@ -1,11 +1,33 @@
-function load()
+function on_load()
   print("Hello, from plugin, baby!")
 end
 
+dry_run = false
+
 function draw(ui)
-  -- Draw your ui here
+  ui:label({ text = "Delay: " .. settings.delay .. " s, language: " .. settings.lang })
+  ui:checkbox({ id = "dry_run", text = "Dry run", checked = dry_run, cb = function(v) dry_run = v end })
+  ui:show()
 end
 
 function registrate()
   
 end
+
+-- Lifecycle hooks, all of them are optional
+
+function on_unload()
+  -- Called before switching to another plugin
+end
+
+function on_config_loaded(name)
+  -- Called after config `name` was opened
+end
+
+function on_config_saved(name)
+  -- Called after config `name` was saved
+end
+
+function on_shutdown()
+  -- Called when application is closing
+end
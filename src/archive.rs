@@ -0,0 +1,74 @@
+use std::{
+    io::{Cursor, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::backup;
+
+// Files of an archive with paths relative to its root
+pub type Entries = Vec<(PathBuf, Vec<u8>)>;
+
+// Reads every file of archive. Archives with entries pointing outside
+// of their root are refused as a whole.
+pub fn read(path: &Path) -> Result<Entries, String> {
+    let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    let mut zip = ZipArchive::new(file).map_err(|e| e.to_string())?;
+    let mut entries = Vec::new();
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).map_err(|e| e.to_string())?;
+        let name = match entry.enclosed_name() {
+            Some(n) => n.to_owned(),
+            None => return Err(format!("unsafe path {} in archive", entry.name())),
+        };
+        if entry.is_dir() {
+            continue;
+        }
+        let mut data = Vec::new();
+        entry
+            .read_to_end(&mut data)
+            .map_err(|e| format!("{}: {}", name.display(), e))?;
+        entries.push((name, data));
+    }
+    Ok(entries)
+}
+
+// Relative path with forward slashes, as stored in archives and records
+pub fn entry_name(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+pub fn write(path: &Path, entries: &[(PathBuf, Vec<u8>)]) -> Result<(), String> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, data) in entries {
+        zip.start_file(entry_name(name), options)
+            .map_err(|e| e.to_string())?;
+        zip.write_all(data).map_err(|e| e.to_string())?;
+    }
+    let data = zip.finish().map_err(|e| e.to_string())?.into_inner();
+    backup::write_atomic(path, &data).map_err(|e| e.to_string())
+}
+
+// Files under dir, their paths are made relative to dir and put under prefix
+pub fn collect(dir: &Path, prefix: &Path) -> Result<Entries, String> {
+    let mut entries = Vec::new();
+    let rd = std::fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    for entry in rd {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let path = entry.path();
+        let name = prefix.join(entry.file_name());
+        if path.is_dir() {
+            entries.extend(collect(&path, &name)?);
+        } else {
+            let data = std::fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            entries.push((name, data));
+        }
+    }
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(entries)
+}
@@ -0,0 +1,232 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Backup copy of a file, timestamp is in YYYYMMDD-HHMMSS form
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backup {
+    pub path: PathBuf,
+    pub timestamp: String,
+}
+
+// Current UTC time as YYYYMMDD-HHMMSS, sorts in chronological order
+pub fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    format_timestamp(secs)
+}
+
+pub fn format_timestamp(secs: u64) -> String {
+    let (days, rem) = ((secs / 86400) as i64, secs % 86400);
+    // days since epoch to civil date
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+// Copies file into dir as <stem>-<timestamp>.<ext>. Returns path of the copy.
+pub fn create(file: &Path, dir: &Path) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = file.extension().unwrap_or_default().to_string_lossy();
+    let ts = timestamp();
+    // several backups within a second are numbered after the latest one
+    let dest = match entries(file, dir).into_iter().rfind(|(t, _, _)| *t == ts) {
+        Some((_, n, _)) => dir.join(format!("{}-{}-{}.{}", stem, ts, n + 1, ext)),
+        None => dir.join(format!("{}-{}.{}", stem, ts, ext)),
+    };
+    std::fs::copy(file, &dest)?;
+    Ok(dest)
+}
+
+// Timestamp as shown to user
+pub fn pretty(ts: &str) -> String {
+    match (ts.get(0..4), ts.get(4..6), ts.get(6..8)) {
+        (Some(y), Some(mo), Some(d)) if ts.len() >= 15 => format!(
+            "{}-{}-{} {}:{}:{} UTC",
+            y,
+            mo,
+            d,
+            &ts[9..11],
+            &ts[11..13],
+            &ts[13..15]
+        ),
+        _ => ts.to_owned(),
+    }
+}
+
+fn is_timestamp(s: &str) -> bool {
+    let b = s.as_bytes();
+    b.len() == 15
+        && b[8] == b'-'
+        && b.iter()
+            .enumerate()
+            .all(|(i, c)| i == 8 || c.is_ascii_digit())
+}
+
+// Backups of file found in dir as (timestamp, number within second, path), oldest first
+fn entries(file: &Path, dir: &Path) -> Vec<(String, u32, PathBuf)> {
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = file.extension().unwrap_or_default().to_string_lossy();
+    let prefix = format!("{}-", stem);
+    let mut backups: Vec<(String, u32, PathBuf)> = match dir.read_dir() {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|e| {
+                let path = e.path();
+                if path.extension().unwrap_or_default().to_string_lossy() != ext {
+                    return None;
+                }
+                let name = path.file_stem()?.to_string_lossy().into_owned();
+                let rest = name.strip_prefix(&prefix)?;
+                let (ts, n) = match rest.get(15..) {
+                    Some("") => (rest, 0),
+                    Some(n) => (&rest[..15], n.strip_prefix('-')?.parse().ok()?),
+                    None => return None,
+                };
+                is_timestamp(ts).then(|| (ts.to_owned(), n, path))
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    backups.sort();
+    backups
+}
+
+// Backups of file found in dir, newest first
+pub fn list(file: &Path, dir: &Path) -> Vec<Backup> {
+    entries(file, dir)
+        .into_iter()
+        .rev()
+        .map(|(timestamp, _, path)| Backup { path, timestamp })
+        .collect()
+}
+
+// Backs file up and removes all but `keep` newest backups
+pub fn rotate(file: &Path, dir: &Path, keep: usize) -> std::io::Result<PathBuf> {
+    let dest = create(file, dir)?;
+    for old in list(file, dir).into_iter().skip(keep) {
+        std::fs::remove_file(old.path)?;
+    }
+    Ok(dest)
+}
+
+// Gives backups of `from` the name of `to`, used when file itself is renamed
+pub fn rename(from: &Path, to: &Path, dir: &Path) -> std::io::Result<()> {
+    let stem = to.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = to.extension().unwrap_or_default().to_string_lossy();
+    for (ts, n, path) in entries(from, dir) {
+        let name = match n {
+            0 => format!("{}-{}.{}", stem, ts, ext),
+            n => format!("{}-{}-{}.{}", stem, ts, n, ext),
+        };
+        std::fs::rename(path, dir.join(name))?;
+    }
+    Ok(())
+}
+
+// Removes every backup of file
+pub fn remove_all(file: &Path, dir: &Path) -> std::io::Result<()> {
+    for (_, _, path) in entries(file, dir) {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+// Writes data to temp file next to path and renames it over path,
+// so path holds either old or new content in full
+pub fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let res = std::fs::File::create(&tmp)
+        .and_then(|mut f| f.write_all(data).and_then(|_| f.sync_all()))
+        .and_then(|_| std::fs::rename(&tmp, path));
+    if res.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(name: &str) -> PathBuf {
+        crate::paths::test_dir(&format!("backup-{}", name))
+    }
+
+    #[test]
+    fn rotation_keeps_newest() {
+        let root = dir("rotate");
+        let (file, backups) = (root.join("a.toml"), root.join("backups"));
+        for i in 0..4 {
+            std::fs::write(&file, i.to_string()).unwrap();
+            rotate(&file, &backups, 2).unwrap();
+        }
+        let kept = list(&file, &backups);
+        assert_eq!(kept.len(), 2);
+        let read = |b: &Backup| std::fs::read_to_string(&b.path).unwrap();
+        assert_eq!(read(&kept[0]), "3");
+        assert_eq!(read(&kept[1]), "2");
+    }
+
+    #[test]
+    fn writes_atomically() {
+        let root = dir("atomic");
+        let file = root.join("a.toml");
+        write_atomic(&file, b"one").unwrap();
+        write_atomic(&file, b"two").unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "two");
+        assert!(!root.join("a.toml.tmp").exists());
+
+        // failed write leaves neither partial file nor temp file
+        let missing = root.join("missing").join("b.toml");
+        assert!(write_atomic(&missing, b"x").is_err());
+        assert_eq!(std::fs::read_dir(&root).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn renames_and_removes_backups() {
+        let root = dir("rename");
+        let backups = root.join("backups");
+        let (a, b, ab) = (
+            root.join("a.toml"),
+            root.join("b.toml"),
+            root.join("a-b.toml"),
+        );
+        std::fs::write(&a, "1").unwrap();
+        std::fs::write(&ab, "x").unwrap();
+        create(&a, &backups).unwrap();
+        create(&a, &backups).unwrap();
+        create(&ab, &backups).unwrap();
+
+        rename(&a, &b, &backups).unwrap();
+        assert!(list(&a, &backups).is_empty());
+        assert_eq!(list(&b, &backups).len(), 2);
+
+        remove_all(&b, &backups).unwrap();
+        assert!(list(&b, &backups).is_empty());
+        // backups of a config whose name starts the same are left alone
+        assert_eq!(list(&ab, &backups).len(), 1);
+    }
+}
@@ -0,0 +1,380 @@
+use std::path::PathBuf;
+
+use crate::{config::Config, harness, install, integrity, paths, plugin, unpack};
+
+const USAGE: &str = "usage: flexar [options] [command]
+
+Without command the GUI is started.
+
+options:
+    --data-dir <path>           keep configs and plugins in <path>
+    --portable                  keep data next to the executable
+
+Data directory may also be set with FLEXAR_HOME. Portable mode is
+turned on as well when file `portable` lies next to the executable,
+data then goes to `flexar-data` beside it, even if FLEXAR_HOME is set.
+
+Signed plugins are checked against public keys in `trusted_keys` of
+data directory. Set `unsigned_plugins = \"refuse\"` in settings.toml
+to run signed plugins only.
+
+commands:
+    paths                       print data directory locations
+    plugins list                list installed plugins
+    plugins unpack [--overwrite | --side-by-side]
+                                unpack bundled plugins, locally modified files
+                                are kept unless an option says otherwise
+    plugins validate <name>     check plugin manifest and script
+    plugins install <archive>   install plugin from zip archive
+    plugins update <archive>    replace installed plugin from zip archive
+    plugins uninstall <name>    remove plugin, its data is kept
+    plugins verify [name]       check checksums and signatures of plugins
+    plugins sign <dir> <key>    list file checksums in manifest of plugin
+                                in <dir> and sign it with secret key
+    keys generate <path>        write new secret key to <path> and its
+                                public key to <path>.pub
+    config list                 list saved configs
+    config show <name>          print config as json
+    test-plugin <name> ...      run plugin headlessly, see `flexar test-plugin`";
+
+// Exit codes
+const OK: i32 = 0;
+const FAILED: i32 = 1;
+const USAGE_ERROR: i32 = 2;
+
+// Takes leading data directory options and sets up paths. Returns remaining args.
+pub fn init_paths(args: Vec<String>) -> Result<Vec<String>, String> {
+    let (options, args) = data_options(args)?;
+    paths::init(paths::Paths::resolve(options.data_dir, options.portable));
+    Ok(args)
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct DataOptions {
+    data_dir: Option<PathBuf>,
+    portable: bool,
+}
+
+// Splits leading data directory options from the rest of args
+fn data_options(args: Vec<String>) -> Result<(DataOptions, Vec<String>), String> {
+    let mut data_dir = None;
+    let mut portable = false;
+    let mut args = args.into_iter().peekable();
+    while let Some(a) = args.peek() {
+        if a == "--portable" {
+            portable = true;
+        } else if a == "--data-dir" {
+            args.next();
+            match args.peek() {
+                Some(dir) => data_dir = Some(PathBuf::from(dir)),
+                None => return Err("missing value for --data-dir".to_owned()),
+            }
+        } else if let Some(dir) = a.strip_prefix("--data-dir=") {
+            data_dir = Some(PathBuf::from(dir));
+        } else {
+            break;
+        }
+        args.next();
+    }
+    Ok((DataOptions { data_dir, portable }, args.collect()))
+}
+
+// Runs subcommand if given. Returns exit code, or None to start the GUI.
+pub fn run(args: &[String]) -> Option<i32> {
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+    let code = match args.as_slice() {
+        [] => return None,
+        ["paths"] => show_paths(),
+        ["plugins", "list"] => plugins_list(),
+        ["plugins", "unpack"] => plugins_unpack(unpack::Resolve::Keep),
+        ["plugins", "unpack", "--overwrite"] => plugins_unpack(unpack::Resolve::Overwrite),
+        ["plugins", "unpack", "--side-by-side"] => plugins_unpack(unpack::Resolve::SideBySide),
+        ["plugins", "validate", name] => plugins_validate(name),
+        ["plugins", "install", path] => plugins_install(path, false),
+        ["plugins", "update", path] => plugins_install(path, true),
+        ["plugins", "uninstall", name] => plugins_uninstall(name),
+        ["plugins", "verify"] => plugins_verify(None),
+        ["plugins", "verify", name] => plugins_verify(Some(name)),
+        ["plugins", "sign", dir, key] => plugins_sign(dir, key),
+        ["keys", "generate", path] => keys_generate(path),
+        ["config", "list"] => config_list(),
+        ["config", "show", name] => config_show(name),
+        ["test-plugin", ..] => {
+            let rest: Vec<String> = args[1..].iter().map(|a| a.to_string()).collect();
+            harness::run(&rest)
+        }
+        ["help" | "--help" | "-h"] => {
+            println!("{}", USAGE);
+            OK
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            USAGE_ERROR
+        }
+    };
+    Some(code)
+}
+
+// Names become file names, so they are checked the same way as in the GUI
+fn valid_name(kind: &str, name: &str) -> bool {
+    match Config::check_name(name) {
+        Ok(_) => true,
+        Err(e) => {
+            eprintln!("{}: {}", kind, e);
+            false
+        }
+    }
+}
+
+// Prints accumulated log to stderr. Returns true if there were errors.
+fn report(log: &str) -> bool {
+    if log.is_empty() {
+        return false;
+    }
+    eprint!("{}", log);
+    true
+}
+
+fn print_json<T: serde::Serialize>(value: &T) -> i32 {
+    match serde_json::to_string_pretty(value) {
+        Ok(s) => {
+            println!("{}", s);
+            OK
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            FAILED
+        }
+    }
+}
+
+fn show_paths() -> i32 {
+    let p = paths::get();
+    print_json(&serde_json::json!({
+        "root": p.root(),
+        "configs": p.configs(),
+        "plugins": p.plugins(),
+        "permissions": p.permissions_file(),
+        "settings": p.settings_file(),
+        "trusted_keys": p.trusted_keys(),
+    }))
+}
+
+fn plugins_list() -> i32 {
+    let mut log = String::new();
+    let mut list = plugin::get_list(&mut log);
+    list.sort();
+    let code = print_json(&list);
+    if report(&log) {
+        FAILED
+    } else {
+        code
+    }
+}
+
+fn plugins_unpack(resolve: unpack::Resolve) -> i32 {
+    let mut log = String::new();
+    let path = paths::get().plugins();
+    if let Err(e) = std::fs::create_dir_all(&path) {
+        eprintln!("{}: {}", path.display(), e);
+        return FAILED;
+    }
+    let mut plans = unpack::plan(&path, &mut log);
+    for f in plans.iter_mut().flat_map(|p| p.files.iter_mut()) {
+        f.resolve = resolve;
+    }
+    let summary = unpack::apply(&path, &plans, &mut log);
+    let failed = report(&log);
+    print_json(&serde_json::json!({
+        "path": path,
+        "unpacked": !failed,
+        "summary": summary,
+    }));
+    if failed {
+        FAILED
+    } else {
+        OK
+    }
+}
+
+fn plugins_validate(name: &str) -> i32 {
+    if !valid_name("plugin", name) {
+        return USAGE_ERROR;
+    }
+    let dir = paths::get().plugin(name);
+    let errors = if dir.is_dir() {
+        plugin::validate(&dir)
+    } else {
+        vec![format!("plugin {} is not installed", name)]
+    };
+    print_json(&serde_json::json!({
+        "name": name,
+        "valid": errors.is_empty(),
+        "errors": errors,
+    }));
+    if errors.is_empty() {
+        OK
+    } else {
+        FAILED
+    }
+}
+
+fn plugins_install(path: &str, update: bool) -> i32 {
+    let mut log = String::new();
+    let res = install::install(std::path::Path::new(path), update, &mut log);
+    eprint!("{}", log);
+    match res {
+        Ok(name) => print_json(&serde_json::json!({
+            "name": name,
+            "version": plugin::installed_version(&name),
+        })),
+        Err(e) => {
+            eprintln!("{}", e);
+            FAILED
+        }
+    }
+}
+
+fn plugins_uninstall(name: &str) -> i32 {
+    if !valid_name("plugin", name) {
+        return USAGE_ERROR;
+    }
+    match install::uninstall(name) {
+        Ok(_) => OK,
+        Err(e) => {
+            eprintln!("{}", e);
+            FAILED
+        }
+    }
+}
+
+fn plugins_verify(name: Option<&str>) -> i32 {
+    let mut log = String::new();
+    let keys = integrity::trusted_keys(&mut log);
+    if name.is_some_and(|n| !valid_name("plugin", n)) {
+        return USAGE_ERROR;
+    }
+    let names = match name {
+        Some(n) if paths::get().plugin(n).is_dir() => vec![n.to_owned()],
+        Some(n) => {
+            eprintln!("plugin {} is not installed", n);
+            return FAILED;
+        }
+        None => {
+            let mut list = plugin::get_list(&mut log);
+            list.sort();
+            list
+        }
+    };
+    report(&log);
+    let mut tampered = false;
+    let statuses: Vec<_> = names
+        .iter()
+        .map(|n| {
+            let status = integrity::check(&paths::get().plugin(n), &keys);
+            let (state, detail) = match &status {
+                integrity::Status::Verified(key) => ("verified", key.as_str()),
+                integrity::Status::Unsigned => ("unsigned", ""),
+                integrity::Status::Tampered(reason) => {
+                    tampered = true;
+                    ("tampered", reason.as_str())
+                }
+            };
+            serde_json::json!({ "name": n, "status": state, "detail": detail })
+        })
+        .collect();
+    let code = print_json(&statuses);
+    if tampered {
+        FAILED
+    } else {
+        code
+    }
+}
+
+fn plugins_sign(dir: &str, key: &str) -> i32 {
+    match integrity::sign(std::path::Path::new(dir), std::path::Path::new(key)) {
+        Ok(_) => OK,
+        Err(e) => {
+            eprintln!("{}", e);
+            FAILED
+        }
+    }
+}
+
+fn keys_generate(path: &str) -> i32 {
+    match integrity::generate_key(std::path::Path::new(path)) {
+        Ok(_) => OK,
+        Err(e) => {
+            eprintln!("{}", e);
+            FAILED
+        }
+    }
+}
+
+fn config_list() -> i32 {
+    let mut log = String::new();
+    let mut list = Config::get_list(&mut log);
+    list.sort();
+    let code = print_json(&list);
+    if report(&log) {
+        FAILED
+    } else {
+        code
+    }
+}
+
+fn config_show(name: &str) -> i32 {
+    if !valid_name("config", name) {
+        return USAGE_ERROR;
+    }
+    let mut log = String::new();
+    let res = Config::try_load(name, &mut log);
+    report(&log);
+    match res {
+        Ok(c) => print_json(&c),
+        Err(e) => {
+            eprintln!("{}", e);
+            FAILED
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn takes_leading_data_options() {
+        let (options, rest) = data_options(args("--portable --data-dir /d config list")).unwrap();
+        assert_eq!(
+            options,
+            DataOptions {
+                data_dir: Some(PathBuf::from("/d")),
+                portable: true
+            }
+        );
+        assert_eq!(rest, args("config list"));
+
+        // options after command belong to it
+        let (options, rest) =
+            data_options(args("--data-dir=/d plugins unpack --portable")).unwrap();
+        assert_eq!(options.data_dir, Some(PathBuf::from("/d")));
+        assert!(!options.portable);
+        assert_eq!(rest, args("plugins unpack --portable"));
+
+        assert!(data_options(args("--data-dir")).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_commands_and_names() {
+        assert_eq!(run(&[]), None);
+        assert_eq!(run(&args("plugins")), Some(USAGE_ERROR));
+        assert_eq!(run(&args("config show ../settings")), Some(USAGE_ERROR));
+        assert_eq!(run(&args("plugins uninstall a/b")), Some(USAGE_ERROR));
+        assert_eq!(run(&args("plugins validate missing")), Some(FAILED));
+    }
+}
@@ -1,5 +1,88 @@
+use std::{cell::Cell, collections::BTreeMap, path::Path};
+
 use serde_derive::{Deserialize, Serialize};
 
+use crate::{backup, form, paths};
+
+// Current layout of config files. Files without `version` key are version 1.
+pub const VERSION: u32 = MIGRATIONS.len() as u32 + 1;
+
+// Number of previous versions kept for every config
+const BACKUPS: usize = 5;
+
+// MIGRATIONS[n] turns layout of version n + 1 into version n + 2.
+// Layout hasn't changed since version 1 yet.
+const MIGRATIONS: [fn(&mut toml::value::Table); 0] = [];
+
+// Editor hints for fields whose limits don't follow from their types
+pub const FORM_HINTS: form::Hints = form::Hints {
+    ranges: &[("pas_len", 1, 128), ("reg_num", 1, 10000)],
+    choices: &[
+        ("proxy_sel", "proxy_files"),
+        ("pas_file", "pas_files"),
+        ("acc_status_file", "acc_status_files"),
+        ("acc_sub_file", "acc_sub_files"),
+        ("acc_posts_file", "acc_posts_files"),
+    ],
+    // edited by plugin selector and plugin settings
+    skip: &["version", "website", "plugin_settings"],
+};
+
+// Fields holding file choices. In files a choice is a plain path or "None",
+// as toml can't write arrays mixing strings and `{ File = "path" }` tables.
+// Those tables are still read, in memory choices keep the enum layout.
+const FILE_FIELDS: &[&str] = &[
+    "proxy_files",
+    "proxy_sel",
+    "pas_files",
+    "pas_file",
+    "acc_status_files",
+    "acc_status_file",
+    "acc_sub_files",
+    "acc_sub_file",
+    "acc_posts_files",
+    "acc_posts_file",
+];
+
+// Turns file choices of table into their file layout, or back
+fn file_layout(table: &mut toml::value::Table, to_file: bool) {
+    fn convert(value: &mut toml::Value, to_file: bool) {
+        match value {
+            toml::Value::Array(items) => items.iter_mut().for_each(|v| convert(v, to_file)),
+            toml::Value::Table(t) if to_file && t.len() == 1 => {
+                if let Some(toml::Value::String(path)) = t.get("File") {
+                    *value = toml::Value::String(path.clone());
+                }
+            }
+            toml::Value::String(path) if !to_file && path != "None" => {
+                let mut t = toml::value::Table::new();
+                t.insert("File".to_owned(), toml::Value::String(std::mem::take(path)));
+                *value = toml::Value::Table(t);
+            }
+            _ => (),
+        }
+    }
+    for f in FILE_FIELDS {
+        if let Some(v) = table.get_mut(*f) {
+            convert(v, to_file);
+        }
+    }
+}
+
+// File choice as shown in messages
+macro_rules! file_choice {
+    ($t:ident) => {
+        impl From<$t> for String {
+            fn from(v: $t) -> Self {
+                match v {
+                    $t::None => "None".to_owned(),
+                    $t::File(s) => s,
+                }
+            }
+        }
+    };
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum RegMethod {
     Phone,
@@ -42,8 +125,109 @@ pub enum PostsFile {
     File(String),
 }
 
+// Problem found while reading config. Line is 1-based.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issue {
+    pub field: String,
+    pub line: Option<usize>,
+    pub reason: String,
+}
+
+impl Issue {
+    fn new(field: &str, line: Option<usize>, reason: impl std::fmt::Display) -> Self {
+        Self {
+            field: field.to_owned(),
+            line,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if !self.field.is_empty() {
+            write!(f, "{}", self.field)?;
+            if self.line.is_some() {
+                write!(f, ", ")?;
+            }
+        }
+        if let Some(l) = self.line {
+            write!(f, "line {}", l)?;
+        }
+        if !self.field.is_empty() || self.line.is_some() {
+            write!(f, ": ")?;
+        }
+        write!(f, "{}", self.reason)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Toml,
+    Json,
+}
+
+impl Format {
+    pub const EXTENSIONS: &'static [&'static str] = &["toml", "json"];
+
+    pub fn from_path(path: &Path) -> Result<Format, String> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Ok(Format::Toml),
+            Some("json") => Ok(Format::Json),
+            _ => Err(format!(
+                "{}: unknown format, expected .toml or .json file",
+                path.display()
+            )),
+        }
+    }
+
+    fn parse(self, data: &str) -> Result<toml::value::Table, Issue> {
+        match self {
+            Format::Toml => toml::from_str(data).map_err(|e| {
+                let line = e.line_col().map(|(l, _)| l + 1);
+                let msg = e.to_string();
+                let reason = msg
+                    .rsplit_once(" at line ")
+                    .map_or(msg.as_str(), |(r, _)| r);
+                Issue::new("", line, reason)
+            }),
+            Format::Json => serde_json::from_str(data).map_err(|e| {
+                let msg = e.to_string();
+                let reason = msg
+                    .rsplit_once(" at line ")
+                    .map_or(msg.as_str(), |(r, _)| r);
+                Issue::new("", Some(e.line()), reason)
+            }),
+        }
+    }
+}
+
+// Key marking exported bundle, config itself is under `config`
+const BUNDLE_KEY: &str = "bundle";
+
+// Plugin the config was exported with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleInfo {
+    // absent values are left out, toml has no null
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plugin: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plugin_version: Option<String>,
+    pub exported: String,
+}
+
+file_choice!(Proxy);
+file_choice!(PasswordFile);
+file_choice!(StatusFile);
+file_choice!(SubscribeFile);
+file_choice!(PostsFile);
+
+// Missing fields are filled from Config::default()
 #[derive(Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
+    pub version: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub website: Option<String>,
     pub proxy_use: bool,
     pub proxy_files: Vec<Proxy>,
@@ -65,11 +249,22 @@ pub struct Config {
     pub acc_posts_file: PostsFile,
     pub reg_method: RegMethod,
     pub reg_num: u32,
+    // Values of manifest-declared settings, keyed by plugin name
+    pub plugin_settings: BTreeMap<String, toml::value::Table>,
+    // Serialized state at last load or save, to detect unsaved changes
+    #[serde(skip)]
+    clean: Option<String>,
+    // Bumped by every edit, comparison with `clean` is redone once per revision
+    #[serde(skip)]
+    revision: u64,
+    #[serde(skip)]
+    dirty: Cell<Option<(u64, bool)>>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            version: VERSION,
             website: None,
             proxy_use: false,
             proxy_files: vec![Proxy::None],
@@ -91,38 +286,256 @@ impl Default for Config {
             acc_posts_file: PostsFile::None,
             reg_method: RegMethod::Phone,
             reg_num: 10,
+            plugin_settings: BTreeMap::new(),
+            clean: None,
+            revision: 0,
+            dirty: Cell::new(None),
         }
     }
 }
 
 impl Config {
-    pub fn load(filename: &String, log: &mut String) -> Config {
-        let mut path = dirs::config_dir().unwrap();
-        path.push(std::path::PathBuf::from(
-            "flexar/configs/".to_owned() + &filename,
-        ));
-        path.set_extension("toml");
-        let data = match std::fs::read_to_string(path) {
-            Ok(e) => e,
-            Err(e) => {
-                log.push_str(&format!("{:?}\n", e));
-                "".to_owned()
+    // Loads config failing on any problem
+    pub fn try_load(filename: &str, log: &mut String) -> Result<Config, String> {
+        let (conf, issues) = Config::read(filename, log);
+        if issues.is_empty() {
+            return Ok(conf);
+        }
+        let path = paths::get().config_file(filename);
+        Err(issues
+            .iter()
+            .map(|i| format!("{}: {}", path.display(), i))
+            .collect::<Vec<String>>()
+            .join("\n"))
+    }
+
+    // Loads config upgrading old layouts in memory and validating every field.
+    // Fields which fail are left at defaults and reported. The file itself is
+    // only written by save(), which keeps the original in backups.
+    pub fn read(filename: &str, log: &mut String) -> (Config, Vec<Issue>) {
+        let path = paths::get().config_file(filename);
+        let data = match std::fs::read_to_string(&path) {
+            Ok(d) => d,
+            Err(e) => return (Config::default(), vec![Issue::new("", None, e)]),
+        };
+        let (mut table, from) = match Format::Toml
+            .parse(&data)
+            .and_then(|t| Config::migrate(t, &data))
+        {
+            Ok(t) => t,
+            Err(issue) => return (Config::default(), vec![issue]),
+        };
+        file_layout(&mut table, false);
+
+        let (mut conf, issues) = Config::from_table(table, &data);
+        if from < VERSION {
+            // left dirty so the upgrade is offered for saving
+            log.push_str(&format!(
+                "{}: upgraded from version {} to {} in memory, save to keep it\n",
+                path.display(),
+                from,
+                VERSION
+            ));
+        } else if issues.is_empty() {
+            conf.mark_clean();
+        }
+        (conf, issues)
+    }
+
+    // Brings table to current layout, returns it with its original version
+    fn migrate(
+        mut table: toml::value::Table,
+        src: &str,
+    ) -> Result<(toml::value::Table, u32), Issue> {
+        let from = match table.get("version") {
+            None => 1,
+            Some(toml::Value::Integer(v)) if *v >= 1 && *v <= VERSION.into() => *v as u32,
+            Some(v) => {
+                return Err(Issue::new(
+                    "version",
+                    key_line(src, "version"),
+                    format!("unsupported version {}, expected 1 to {}", v, VERSION),
+                ))
             }
         };
-        let decode: Config = match toml::from_str(&data) {
-            Ok(e) => e,
-            Err(e) => {
-                log.push_str(&format!("{:?}\n", e));
-                Config::default()
+        for m in &MIGRATIONS[from as usize - 1..] {
+            m(&mut table);
+        }
+        table.insert("version".to_owned(), toml::Value::Integer(VERSION.into()));
+        Ok((table, from))
+    }
+
+    // Reads config or bundle from any path, format is picked by extension
+    pub fn import(path: &Path) -> (Config, Vec<Issue>, Option<BundleInfo>) {
+        let fail = |issue| (Config::default(), vec![issue], None);
+        let format = match Format::from_path(path) {
+            Ok(f) => f,
+            Err(e) => return fail(Issue::new("", None, e)),
+        };
+        let data = match std::fs::read_to_string(path) {
+            Ok(d) => d,
+            Err(e) => return fail(Issue::new("", None, e)),
+        };
+        let mut table = match format.parse(&data) {
+            Ok(t) => t,
+            Err(issue) => return fail(issue),
+        };
+
+        let mut bundle = None;
+        if let Some(info) = table.remove(BUNDLE_KEY) {
+            match info.try_into::<BundleInfo>() {
+                Ok(b) => bundle = Some(b),
+                Err(e) => return fail(Issue::new(BUNDLE_KEY, key_line(&data, BUNDLE_KEY), e)),
+            }
+            table = match table.remove("config") {
+                Some(toml::Value::Table(t)) => t,
+                _ => {
+                    return fail(Issue::new(
+                        "config",
+                        key_line(&data, "config"),
+                        "bundle has no config table",
+                    ))
+                }
+            };
+        }
+        match Config::migrate(table, &data) {
+            Ok((mut table, _)) => {
+                file_layout(&mut table, false);
+                let (conf, issues) = Config::from_table(table, &data);
+                (conf, issues, bundle)
             }
+            Err(issue) => fail(issue),
+        }
+    }
+
+    // Writes config to any path, format is picked by extension. With bundle
+    // info the plugin and its version are stored along with the config.
+    pub fn export(&mut self, path: &Path, bundle: Option<BundleInfo>) -> Result<(), String> {
+        let format = Format::from_path(path)?;
+        let mut table = self.file_table()?;
+        if let Some(bundle) = bundle {
+            let info = toml::Value::try_from(bundle).map_err(|e| e.to_string())?;
+            let config = std::mem::take(&mut table);
+            table.insert(BUNDLE_KEY.to_owned(), info);
+            table.insert("config".to_owned(), toml::Value::Table(config));
+        }
+        let table = toml::Value::Table(table);
+        let data = match format {
+            Format::Toml => toml::to_string(&table).map_err(|e| e.to_string())?,
+            Format::Json => serde_json::to_string_pretty(&table).map_err(|e| e.to_string())?,
         };
+        backup::write_atomic(path, data.as_bytes()).map_err(|e| e.to_string())
+    }
+
+    // Table in the layout config files use, see FILE_FIELDS
+    fn file_table(&mut self) -> Result<toml::value::Table, String> {
+        self.version = VERSION;
+        let mut table = self.to_table()?;
+        file_layout(&mut table, true);
+        Ok(table)
+    }
 
-        decode
+    // toml::Value can't hold enum variants with data, so table is read through json
+    fn from_toml(table: toml::value::Table) -> Result<Config, String> {
+        let json = serde_json::to_value(table).map_err(|e| e.to_string())?;
+        serde_json::from_value(json).map_err(|e| e.to_string())
+    }
+
+    // Applies fields one by one on top of defaults, so a bad field doesn't spoil the rest
+    fn from_table(table: toml::value::Table, src: &str) -> (Config, Vec<Issue>) {
+        let mut issues = Vec::new();
+        let mut merged = match Config::default().to_table() {
+            Ok(t) => t,
+            Err(e) => return (Config::default(), vec![Issue::new("", None, e)]),
+        };
+        for (key, value) in table {
+            let mut candidate = merged.clone();
+            candidate.insert(key.clone(), value);
+            match Config::from_toml(candidate.clone()) {
+                Ok(_) => merged = candidate,
+                Err(e) if e.starts_with("unknown field") => {
+                    issues.push(Issue::new(&key, key_line(src, &key), "unknown field"))
+                }
+                Err(e) => issues.push(Issue::new(&key, key_line(src, &key), e)),
+            }
+        }
+        let conf = Config::from_toml(merged).unwrap_or_default();
+        issues.extend(
+            conf.check()
+                .into_iter()
+                .map(|(field, reason)| Issue::new(field, key_line(src, field), reason)),
+        );
+        (conf, issues)
+    }
+
+    // Checks between fields, returns field name and reason
+    fn check(&self) -> Vec<(&'static str, String)> {
+        fn selected<T: PartialEq + Clone + Into<String>>(
+            out: &mut Vec<(&'static str, String)>,
+            field: &'static str,
+            list: &'static str,
+            sel: &T,
+            files: &[T],
+        ) {
+            if !files.contains(sel) {
+                out.push((
+                    field,
+                    format!("\"{}\" is not listed in {}", sel.clone().into(), list),
+                ));
+            }
+        }
+        let mut out = Vec::new();
+        selected(
+            &mut out,
+            "proxy_sel",
+            "proxy_files",
+            &self.proxy_sel,
+            &self.proxy_files,
+        );
+        selected(
+            &mut out,
+            "pas_file",
+            "pas_files",
+            &self.pas_file,
+            &self.pas_files,
+        );
+        selected(
+            &mut out,
+            "acc_status_file",
+            "acc_status_files",
+            &self.acc_status_file,
+            &self.acc_status_files,
+        );
+        selected(
+            &mut out,
+            "acc_sub_file",
+            "acc_sub_files",
+            &self.acc_sub_file,
+            &self.acc_sub_files,
+        );
+        selected(
+            &mut out,
+            "acc_posts_file",
+            "acc_posts_files",
+            &self.acc_posts_file,
+            &self.acc_posts_files,
+        );
+        if self.pas_type == PasswordType::Generate && !self.use_custom_pas {
+            if self.pas_len == 0 {
+                out.push(("pas_len", "password length must be positive".to_owned()));
+            }
+            if !self.pas_letters && !self.pas_nums {
+                out.push((
+                    "pas_nums",
+                    "generated password needs letters or numbers".to_owned(),
+                ));
+            }
+        }
+        out
     }
 
     pub fn get_list(log: &mut String) -> Vec<String> {
-        let mut path = dirs::config_dir().unwrap();
-        path.push(std::path::PathBuf::from("flexar/configs/".to_owned()));
+        let path = paths::get().configs();
         if !path.exists() {
             match std::fs::create_dir_all(&path) {
                 Ok(_) => (),
@@ -139,16 +552,16 @@ impl Config {
 
         let mut file: Vec<String> = Vec::new();
         if let Some(entries) = files {
-            for e in entries {
-                if let Ok(entry) = e {
-                    let mut path = entry.path();
-                    if let Ok(ftype) = entry.file_type() {
-                        if ftype.is_file() == true {
-                            if let Some(ext) = path.extension() {
-                                if ext == "toml" {
-                                    path.set_extension("");
-                                    file.push(String::from(path.file_name().unwrap().to_str().unwrap()));
-                                }
+            for entry in entries.flatten() {
+                let mut path = entry.path();
+                if let Ok(ftype) = entry.file_type() {
+                    if ftype.is_file() {
+                        if let Some(ext) = path.extension() {
+                            if ext == "toml" {
+                                path.set_extension("");
+                                file.push(String::from(
+                                    path.file_name().unwrap().to_str().unwrap(),
+                                ));
                             }
                         }
                     }
@@ -158,22 +571,426 @@ impl Config {
         file
     }
 
-    pub fn save(&mut self, filename: &String, log: &mut String) {
-        let mut path = dirs::config_dir().unwrap();
-        path.push(std::path::PathBuf::from(
-            "flexar/configs/".to_owned() + &filename,
-        ));
-        path.set_extension("toml");
-        let data = match toml::to_vec(&self) {
-            Ok(d) => d,
+    // Goes through json like from_toml, toml can't serialize file choices
+    pub fn to_table(&self) -> Result<toml::value::Table, String> {
+        let json = serde_json::to_value(self).map_err(|e| e.to_string())?;
+        serde_json::from_value(json).map_err(|e| e.to_string())
+    }
+
+    // Sets fields to given values, fields without value are reset to defaults
+    pub fn set_fields(
+        &mut self,
+        fields: &[String],
+        values: &toml::value::Table,
+    ) -> Result<(), String> {
+        let mut table = self.to_table()?;
+        for f in fields {
+            match values.get(f) {
+                Some(v) => table.insert(f.clone(), v.clone()),
+                None => table.remove(f),
+            };
+        }
+        let mut conf = Config::from_toml(table)?;
+        conf.clean = std::mem::take(&mut self.clean);
+        conf.revision = self.revision + 1;
+        *self = conf;
+        Ok(())
+    }
+
+    pub fn set_website(&mut self, name: Option<String>) {
+        self.website = name;
+        self.revision += 1;
+    }
+
+    pub fn set_plugin_settings(&mut self, plugin: &str, values: toml::value::Table) {
+        self.plugin_settings.insert(plugin.to_owned(), values);
+        self.revision += 1;
+    }
+
+    // None if config can't be serialized, it then counts as dirty
+    fn snapshot(&self) -> Option<String> {
+        serde_json::to_string(self).ok()
+    }
+
+    // Current state matches the file
+    pub fn mark_clean(&mut self) {
+        self.clean = self.snapshot();
+        self.dirty.set(Some((self.revision, false)));
+    }
+
+    // Edits made through methods are seen here, public fields changed directly are not
+    pub fn is_dirty(&self) -> bool {
+        match self.dirty.get() {
+            Some((rev, dirty)) if rev == self.revision => dirty,
+            _ => {
+                let dirty = match (self.snapshot(), &self.clean) {
+                    (Some(now), Some(clean)) => now != *clean,
+                    _ => true,
+                };
+                self.dirty.set(Some((self.revision, dirty)));
+                dirty
+            }
+        }
+    }
+
+    pub fn exists(name: &str) -> bool {
+        paths::get().config_file(name).is_file()
+    }
+
+    // Config names become file names, so path separators are not allowed
+    pub fn check_name(name: &str) -> Result<(), String> {
+        if name.trim().is_empty() {
+            Err("name is empty".to_owned())
+        } else if name.starts_with('.') || name.contains(['/', '\\', ':']) {
+            Err(format!("invalid name {}", name))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_target(to: &str) -> Result<std::path::PathBuf, String> {
+        Config::check_name(to)?;
+        let path = paths::get().config_file(to);
+        if path.exists() {
+            return Err(format!("config {} already exists", to));
+        }
+        Ok(path)
+    }
+
+    // Backups follow the config to its new name
+    pub fn rename(from: &str, to: &str) -> Result<(), String> {
+        let dest = Config::check_target(to)?;
+        let src = paths::get().config_file(from);
+        std::fs::rename(&src, &dest).map_err(|e| e.to_string())?;
+        backup::rename(&src, &dest, &paths::get().config_backups())
+            .map_err(|e| format!("config renamed, but its backups weren't: {}", e))
+    }
+
+    pub fn duplicate(from: &str, to: &str) -> Result<(), String> {
+        let dest = Config::check_target(to)?;
+        std::fs::copy(paths::get().config_file(from), dest)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    // Backups are removed along with the config
+    pub fn delete(name: &str) -> Result<(), String> {
+        let path = paths::get().config_file(name);
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        backup::remove_all(&path, &paths::get().config_backups())
+            .map_err(|e| format!("config deleted, but its backups weren't: {}", e))
+    }
+
+    // Saves config keeping previous version in backups
+    pub fn save(&mut self, filename: &str, log: &mut String) -> bool {
+        let path = paths::get().config_file(filename);
+        if path.exists() {
+            if let Err(e) = backup::rotate(&path, &paths::get().config_backups(), BACKUPS) {
+                log.push_str(&format!("{}: backup failed: {}\n", path.display(), e));
+                return false;
+            }
+        }
+        match self.write(&path) {
+            Ok(_) => {
+                self.mark_clean();
+                true
+            }
             Err(e) => {
-                log.push_str(&format!("{:?}\n", e));
-                vec![]
+                log.push_str(&format!("{}: {}\n", path.display(), e));
+                false
             }
+        }
+    }
+
+    // Existing file is kept untouched if config can't be serialized
+    fn write(&mut self, path: &std::path::Path) -> Result<(), String> {
+        let table = toml::Value::Table(self.file_table()?);
+        let data = toml::to_vec(&table).map_err(|e| e.to_string())?;
+        std::fs::create_dir_all(paths::get().configs()).map_err(|e| e.to_string())?;
+        backup::write_atomic(path, &data).map_err(|e| e.to_string())
+    }
+
+    // Saved versions of config, newest first
+    pub fn backups(name: &str) -> Vec<backup::Backup> {
+        backup::list(
+            &paths::get().config_file(name),
+            &paths::get().config_backups(),
+        )
+    }
+
+    // Replaces config with backup, current version is backed up first
+    pub fn restore(name: &str, from: &backup::Backup) -> Result<(), String> {
+        let data = std::fs::read(&from.path).map_err(|e| e.to_string())?;
+        Config::replace(name, &data)
+    }
+
+    // Writes raw config file, current version is backed up first
+    pub fn replace(name: &str, data: &[u8]) -> Result<(), String> {
+        Config::check_name(name)?;
+        let path = paths::get().config_file(name);
+        std::fs::create_dir_all(paths::get().configs()).map_err(|e| e.to_string())?;
+        if path.exists() {
+            backup::rotate(&path, &paths::get().config_backups(), BACKUPS)
+                .map_err(|e| e.to_string())?;
+        }
+        backup::write_atomic(&path, data).map_err(|e| e.to_string())
+    }
+}
+
+// Finds line where key or table is defined, in TOML or JSON
+fn key_line(src: &str, key: &str) -> Option<usize> {
+    src.lines()
+        .position(|l| {
+            let l = l.trim_start();
+            let rest = l
+                .strip_prefix(key)
+                .or_else(|| l.strip_prefix('[').and_then(|t| t.strip_prefix(key)))
+                .or_else(|| {
+                    l.strip_prefix('"')
+                        .and_then(|t| t.strip_prefix(key))
+                        .and_then(|t| t.strip_prefix('"'))
+                });
+            rest.is_some_and(|r| {
+                let r = r.trim_start();
+                r.starts_with(['=', '.', ']', ':'])
+            })
+        })
+        .map(|n| n + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(src: &str) -> (Config, Vec<Issue>) {
+        let mut table = Format::Toml.parse(src).unwrap();
+        file_layout(&mut table, false);
+        Config::from_table(table, src)
+    }
+
+    // toml::Value can't hold { File = "path" }, yet such configs must still load
+    #[test]
+    fn reads_file_choices() {
+        let src =
+            "proxy_files = [\"None\", { File = \"p.txt\" }]\nproxy_sel = { File = \"p.txt\" }\n";
+        let (conf, issues) = parse(src);
+        assert!(issues.is_empty(), "{:?}", issues);
+        assert_eq!(conf.proxy_sel, Proxy::File("p.txt".to_owned()));
+        assert_eq!(
+            conf.proxy_files,
+            vec![Proxy::None, Proxy::File("p.txt".to_owned())]
+        );
+
+        // files keep plain paths
+        let (plain, issues) = parse("proxy_files = [\"None\", \"p.txt\"]\nproxy_sel = \"p.txt\"\n");
+        assert!(issues.is_empty(), "{:?}", issues);
+        assert_eq!(plain.proxy_files, conf.proxy_files);
+
+        let (conf, issues) = parse("proxy_sel = { Url = \"x\" }\n");
+        assert_eq!(issues.len(), 1, "{:?}", issues);
+        assert_eq!(issues[0].field, "proxy_sel");
+        assert_eq!(conf.proxy_sel, Proxy::None);
+    }
+
+    #[test]
+    fn finds_key_lines() {
+        let src = "version = 1\n# pas_len = 1\npas_lenx = 2\n  pas_len = 3\n[plugin_settings.vk]\n";
+        assert_eq!(key_line(src, "pas_len"), Some(4));
+        assert_eq!(key_line(src, "plugin_settings"), Some(5));
+        assert_eq!(key_line(src, "reg_num"), None);
+        let json = "{\n  \"version\": 1,\n  \"reg_num\" : 3\n}";
+        assert_eq!(key_line(json, "reg_num"), Some(3));
+
+        let (_, issues) = parse("version = 1\n\nreg_num = \"ten\"\n");
+        assert_eq!(issues.len(), 1, "{:?}", issues);
+        assert_eq!(issues[0].field, "reg_num");
+        assert_eq!(issues[0].line, Some(3));
+    }
+
+    #[test]
+    fn checks_fields_against_each_other() {
+        let (_, issues) =
+            parse("proxy_sel = \"p.txt\"\npas_len = 0\npas_letters = false\npas_nums = false\n");
+        let found: Vec<_> = issues.iter().map(|i| (i.field.as_str(), i.line)).collect();
+        assert_eq!(
+            found,
+            vec![
+                ("proxy_sel", Some(1)),
+                ("pas_len", Some(2)),
+                ("pas_nums", Some(4))
+            ]
+        );
+        assert!(issues[0].reason.contains("not listed in proxy_files"));
+
+        // password rules only matter for generated passwords
+        let (_, issues) = parse("use_custom_pas = true\npas_len = 0\n");
+        assert!(issues.is_empty(), "{:?}", issues);
+    }
+
+    // Exports to toml, then json, then toml again through files, returns both toml texts
+    fn round_trip(name: &str, conf: &mut Config, bundle: Option<BundleInfo>) -> (String, String) {
+        let dir = paths::test_dir(&format!("config-{}", name));
+        let (first, json, last) = (
+            dir.join("first.toml"),
+            dir.join("config.json"),
+            dir.join("last.toml"),
+        );
+        conf.export(&first, bundle.clone()).unwrap();
+        let mut prev = first.clone();
+        for next in [&json, &last] {
+            let (mut c, issues, b) = Config::import(&prev);
+            assert!(issues.is_empty(), "{}: {:?}", prev.display(), issues);
+            assert_eq!(b, bundle);
+            c.export(next, b).unwrap();
+            prev = next.clone();
+        }
+        (
+            std::fs::read_to_string(first).unwrap(),
+            std::fs::read_to_string(last).unwrap(),
+        )
+    }
+
+    #[test]
+    fn default_config_survives_json() {
+        let (first, last) = round_trip("default", &mut Config::default(), None);
+        assert_eq!(first, last);
+
+        let bundle = BundleInfo {
+            plugin: None,
+            plugin_version: None,
+            exported: "20261019-120000".to_owned(),
         };
-        match std::fs::write(path, data) {
-            Ok(_) => (),
-            Err(e) => log.push_str(&format!("{:?}\n", e)),
+        let (first, last) = round_trip("default-bundle", &mut Config::default(), Some(bundle));
+        assert_eq!(first, last);
+    }
+
+    #[test]
+    fn config_with_plugin_survives_json() {
+        let mut conf = Config {
+            website: Some("vk".to_owned()),
+            reg_num: 3,
+            ..Config::default()
         };
+        let mut settings = toml::value::Table::new();
+        settings.insert("delay".to_owned(), toml::Value::Integer(7));
+        settings.insert("lang".to_owned(), toml::Value::String("en".to_owned()));
+        conf.plugin_settings.insert("vk".to_owned(), settings);
+        let bundle = BundleInfo {
+            plugin: Some("vk".to_owned()),
+            plugin_version: Some("0.1.1".to_owned()),
+            exported: "20261019-120000".to_owned(),
+        };
+        let (first, last) = round_trip("plugin", &mut conf, Some(bundle));
+        assert_eq!(first, last);
+        assert!(first.contains("delay = 7"), "{}", first);
+    }
+
+    fn with_proxy_file() -> Config {
+        let proxy = Proxy::File("p.txt".to_owned());
+        Config {
+            website: Some("vk".to_owned()),
+            proxy_files: vec![Proxy::None, proxy.clone()],
+            proxy_sel: proxy,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn edits_keep_other_fields() {
+        let mut conf = with_proxy_file();
+        let table = conf.to_table().unwrap();
+        let mut values = table.clone();
+        values.insert("reg_num".to_owned(), toml::Value::Integer(42));
+        conf.set_fields(&["reg_num".to_owned()], &values).unwrap();
+        assert_eq!(conf.reg_num, 42);
+        assert_eq!(conf.website.as_deref(), Some("vk"));
+        assert_eq!(conf.proxy_sel, Proxy::File("p.txt".to_owned()));
+
+        let mut after = conf.to_table().unwrap();
+        after.insert("reg_num".to_owned(), toml::Value::Integer(10));
+        assert_eq!(after, table);
+    }
+
+    #[test]
+    fn config_with_files_survives_json() {
+        let (first, last) = round_trip("files", &mut with_proxy_file(), None);
+        assert_eq!(first, last);
+        assert!(first.contains("\"p.txt\""), "{}", first);
+    }
+
+    #[test]
+    fn saves_config_with_files() {
+        let name = "save-files";
+        let mut conf = with_proxy_file();
+        assert!(conf.save(name, &mut String::new()));
+        assert!(!conf.is_dirty());
+
+        let mut log = String::new();
+        let read = Config::try_load(name, &mut log).unwrap();
+        assert!(log.is_empty(), "{}", log);
+        assert_eq!(read.proxy_files, conf.proxy_files);
+        assert_eq!(read.proxy_sel, conf.proxy_sel);
+        assert_eq!(read.website, conf.website);
+    }
+
+    #[test]
+    fn restores_backup() {
+        let name = "restore";
+        let mut log = String::new();
+        let mut conf = Config::default();
+        assert!(conf.save(name, &mut log));
+        conf.reg_num = 42;
+        assert!(conf.save(name, &mut log));
+        let backups = Config::backups(name);
+        assert_eq!(backups.len(), 1);
+
+        Config::restore(name, &backups[0]).unwrap();
+        assert_eq!(Config::try_load(name, &mut log).unwrap().reg_num, 10);
+        // version being replaced is kept too
+        assert_eq!(Config::backups(name).len(), 2);
+        assert!(log.is_empty(), "{}", log);
+    }
+
+    #[test]
+    fn tracks_unsaved_edits() {
+        let mut conf = Config::default();
+        conf.mark_clean();
+        assert!(!conf.is_dirty());
+
+        let mut values = toml::value::Table::new();
+        values.insert("reg_num".to_owned(), toml::Value::Integer(42));
+        conf.set_fields(&["reg_num".to_owned()], &values).unwrap();
+        assert!(conf.is_dirty());
+        assert_eq!(conf.reg_num, 42);
+
+        // going back to the saved value is clean again
+        values.insert("reg_num".to_owned(), toml::Value::Integer(10));
+        conf.set_fields(&["reg_num".to_owned()], &values).unwrap();
+        assert!(!conf.is_dirty());
+
+        conf.set_website(Some("vk".to_owned()));
+        assert!(conf.is_dirty());
+        conf.mark_clean();
+        assert!(!conf.is_dirty());
+    }
+
+    #[test]
+    fn tracks_edits_of_config_with_files() {
+        let mut conf = with_proxy_file();
+        conf.mark_clean();
+        assert!(!conf.is_dirty());
+
+        let mut values = toml::value::Table::new();
+        values.insert(
+            "proxy_files".to_owned(),
+            toml::Value::Array(vec![toml::Value::String("None".to_owned())]),
+        );
+        values.insert(
+            "proxy_sel".to_owned(),
+            toml::Value::String("None".to_owned()),
+        );
+        let fields = ["proxy_files".to_owned(), "proxy_sel".to_owned()];
+        conf.set_fields(&fields, &values).unwrap();
+        assert!(conf.is_dirty());
     }
 }
@@ -1,4 +1,9 @@
-use crate::config;
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    backup, config, file_browser::FileBrowser, install, paths, permissions::Capability, plugin,
+    profile, repo, settings::Settings, unpack, version::Version,
+};
 
 pub fn about(ctx: &eframe::egui::CtxRef, open: &mut bool) {
     eframe::egui::Window::new("About")
@@ -8,20 +13,129 @@ pub fn about(ctx: &eframe::egui::CtxRef, open: &mut bool) {
         });
 }
 
+// Asks user to approve capabilities requested by plugin.
+// Returns Some(true) if allowed, Some(false) if denied and None while undecided.
+pub fn permissions(
+    ctx: &eframe::egui::CtxRef,
+    plugin: &str,
+    version: &str,
+    caps: &[Capability],
+) -> Option<bool> {
+    let mut decision = None;
+    eframe::egui::Window::new("Plugin permissions")
+        .collapsible(false)
+        .show(ctx, |ui| {
+            ui.label(format!(
+                "Plugin {} {} requests the following permissions:",
+                plugin, version
+            ));
+            for c in caps {
+                ui.label(format!("• {} — {}", c.name(), c.description()));
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Allow").clicked() {
+                    decision = Some(true);
+                }
+                if ui.button("Deny").clicked() {
+                    decision = Some(false);
+                }
+            });
+        });
+    decision
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unsaved {
+    Save,
+    Discard,
+    Cancel,
+}
+
+// Asks what to do with unsaved changes. Returns None while undecided.
+pub fn unsaved(ctx: &eframe::egui::CtxRef, name: &str) -> Option<Unsaved> {
+    let mut decision = None;
+    eframe::egui::Window::new("Unsaved changes")
+        .collapsible(false)
+        .show(ctx, |ui| {
+            if name.is_empty() {
+                ui.label("Current config has unsaved changes.");
+            } else {
+                ui.label(format!("Config {} has unsaved changes.", name));
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    decision = Some(Unsaved::Save);
+                }
+                if ui.button("Discard").clicked() {
+                    decision = Some(Unsaved::Discard);
+                }
+                if ui.button("Cancel").clicked() {
+                    decision = Some(Unsaved::Cancel);
+                }
+            });
+        });
+    decision
+}
+
 #[derive(Debug, Clone, PartialEq)]
 enum ConfigSelect {
     None,
     File(String),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FileAction {
+    Rename,
+    Duplicate,
+    Delete,
+    Restore,
+}
+
+// Config with problems waiting for user decision
+struct Invalid {
+    // file name shown to user
+    title: String,
+    // name config gets once opened, imported configs stay untitled
+    name: String,
+    conf: config::Config,
+    issues: Vec<config::Issue>,
+}
+
+impl Invalid {
+    fn new(name: &str, conf: config::Config, issues: Vec<config::Issue>) -> Self {
+        Self {
+            title: name.to_owned(),
+            name: name.to_owned(),
+            conf,
+            issues,
+        }
+    }
+}
+
 pub struct ConfigDialog {
     files: Vec<ConfigSelect>,
     file: ConfigSelect,
-    pub open: bool,
+    open: bool,
     close_open: bool,
-    pub save: bool,
+    save: bool,
     close_save: bool,
     save_name: String,
+    // Name typed in save dialog, becomes save_name once saved under it
+    save_edit: String,
+    // Current config keeps its name until this one is opened
+    invalid: Option<Invalid>,
+    // Action on selected file waiting for new name or confirmation
+    action: Option<FileAction>,
+    action_name: String,
+    // Backups of selected file listed for restore
+    backups: Vec<backup::Backup>,
+    restore_sel: Option<usize>,
+    // Save name is taken, waiting for confirmation
+    overwrite: bool,
+    import: Option<FileBrowser>,
+    export: Option<FileBrowser>,
+    // Bundle info of imported config, checked once config is applied
+    bundle: Option<config::BundleInfo>,
 }
 
 impl Default for ConfigDialog {
@@ -34,15 +148,213 @@ impl Default for ConfigDialog {
             save: false,
             close_save: false,
             save_name: String::new(),
+            save_edit: String::new(),
+            invalid: None,
+            action: None,
+            action_name: String::new(),
+            backups: Vec::new(),
+            restore_sel: None,
+            overwrite: false,
+            import: None,
+            export: None,
+            bundle: None,
         }
     }
 }
 
 impl ConfigDialog {
-    pub fn load(&mut self, log: &mut String) {
-        let files = config::Config::get_list(log);
-        for f in files {
-            self.files.push(ConfigSelect::File(f.clone()));
+    // Rereads configs directory, selection is dropped if file is gone
+    pub fn refresh(&mut self, log: &mut String) {
+        let mut files = config::Config::get_list(log);
+        files.sort();
+        self.files = vec![ConfigSelect::None];
+        self.files.extend(files.into_iter().map(ConfigSelect::File));
+        if !self.files.contains(&self.file) {
+            self.file = ConfigSelect::None;
+        }
+    }
+
+    pub fn open_dialog(&mut self, log: &mut String) {
+        self.refresh(log);
+        self.action = None;
+        self.open = true;
+    }
+
+    pub fn save_dialog(&mut self, log: &mut String) {
+        self.refresh(log);
+        self.save_edit = self.save_name.clone();
+        self.overwrite = false;
+        self.save = true;
+    }
+
+    // Opens config by name without showing the dialog. Config with
+    // problems isn't returned, user is asked about it as usual.
+    pub fn open_named(
+        &mut self,
+        name: &str,
+        plugin: &mut plugin::Plugin,
+        log: &mut String,
+        lua_log: Rc<RefCell<String>>,
+    ) -> Option<config::Config> {
+        self.file = ConfigSelect::File(name.to_owned());
+        self.bundle = None;
+        let (c, issues) = config::Config::read(name, log);
+        if issues.is_empty() {
+            self.save_name = name.to_owned();
+            Some(ConfigDialog::apply(name, c, plugin, log, lua_log))
+        } else {
+            self.invalid = Some(Invalid::new(name, c, issues));
+            None
+        }
+    }
+
+    // Opens config of profile with plugin and its settings taken from profile
+    pub fn open_profile(
+        &mut self,
+        p: &profile::Profile,
+        plugin: &mut plugin::Plugin,
+        log: &mut String,
+        lua_log: Rc<RefCell<String>>,
+    ) -> Option<config::Config> {
+        if !config::Config::exists(&p.config) {
+            log.push_str(&format!("Config {} doesn't exist.\n", p.config));
+            return None;
+        }
+        self.file = ConfigSelect::File(p.config.clone());
+        self.bundle = None;
+        let (mut c, issues) = config::Config::read(&p.config, log);
+        if issues.is_empty() {
+            p.apply(&mut c);
+            self.save_name = p.config.clone();
+            Some(ConfigDialog::apply(&p.config, c, plugin, log, lua_log))
+        } else {
+            self.invalid = Some(Invalid::new(&p.config, c, issues));
+            None
+        }
+    }
+
+    // Name of current config, empty if it was never saved
+    pub fn name(&self) -> &str {
+        &self.save_name
+    }
+
+    pub fn is_saving(&self) -> bool {
+        self.save
+    }
+
+    fn save_as(
+        name: &str,
+        conf: &mut config::Config,
+        plugin: &plugin::Plugin,
+        log: &mut String,
+    ) -> bool {
+        if !conf.save(name, log) {
+            return false;
+        }
+        log.push_str(&format!("Config file {} was saved.\n", name));
+        plugin.config_saved(name, log);
+        true
+    }
+
+    // Saves config under its current name without asking
+    pub fn save_current(
+        &mut self,
+        conf: &mut config::Config,
+        plugin: &plugin::Plugin,
+        log: &mut String,
+    ) -> bool {
+        let saved = ConfigDialog::save_as(&self.save_name, conf, plugin, log);
+        if saved {
+            self.refresh(log);
+        }
+        saved
+    }
+
+    pub fn import_dialog(&mut self) {
+        self.import = Some(FileBrowser::new(
+            "Import config",
+            "",
+            config::Format::EXTENSIONS,
+        ));
+    }
+
+    pub fn export_dialog(&mut self) {
+        let name = if self.save_name.is_empty() {
+            "config"
+        } else {
+            &self.save_name
+        };
+        self.export = Some(
+            FileBrowser::new(
+                "Export config",
+                &format!("{}.toml", name),
+                config::Format::EXTENSIONS,
+            )
+            .with_option("Bundle with plugin version", true),
+        );
+    }
+
+    // Warns if imported bundle was made with another plugin version
+    fn check_bundle(bundle: &config::BundleInfo, plugin: &plugin::Plugin, log: &mut String) {
+        let name = match &bundle.plugin {
+            Some(n) => n,
+            None => return,
+        };
+        let installed = if plugin.name() == name {
+            plugin.version()
+        } else {
+            ""
+        };
+        match &bundle.plugin_version {
+            _ if installed.is_empty() => log.push_str(&format!(
+                "Config was exported with plugin {}, which isn't loaded.\n",
+                name
+            )),
+            Some(v) if v != installed => log.push_str(&format!(
+                "Config was exported with {} {}, installed version is {}.\n",
+                name, v, installed
+            )),
+            _ => (),
+        }
+    }
+
+    // Runs confirmed action, returns new selection
+    fn run_action(
+        action: FileAction,
+        from: &str,
+        to: &str,
+        restore: Option<&backup::Backup>,
+        log: &mut String,
+    ) -> Result<ConfigSelect, String> {
+        match action {
+            FileAction::Rename => {
+                config::Config::rename(from, to)?;
+                log.push_str(&format!("Config file {} was renamed to {}.\n", from, to));
+                Ok(ConfigSelect::File(to.to_owned()))
+            }
+            FileAction::Duplicate => {
+                config::Config::duplicate(from, to)?;
+                log.push_str(&format!("Config file {} was copied to {}.\n", from, to));
+                Ok(ConfigSelect::File(to.to_owned()))
+            }
+            FileAction::Delete => {
+                config::Config::delete(from)?;
+                log.push_str(&format!(
+                    "Config file {} was deleted with its saved versions.\n",
+                    from
+                ));
+                Ok(ConfigSelect::None)
+            }
+            FileAction::Restore => {
+                let b = restore.ok_or_else(|| "select a version to restore".to_owned())?;
+                config::Config::restore(from, b)?;
+                log.push_str(&format!(
+                    "Config file {} was restored to version from {}.\n",
+                    from,
+                    backup::pretty(&b.timestamp)
+                ));
+                Ok(ConfigSelect::File(from.to_owned()))
+            }
         }
     }
 
@@ -50,12 +362,14 @@ impl ConfigDialog {
         &mut self,
         ctx: &eframe::egui::CtxRef,
         conf: &mut config::Config,
+        plugin: &plugin::Plugin,
         log: &mut String,
     ) {
         if self.close_save {
             self.close_save = false;
             self.save = false;
         }
+        let mut saved = false;
         if self.save {
             eframe::egui::Window::new("Save config file")
                 .open(&mut self.save)
@@ -66,13 +380,24 @@ impl ConfigDialog {
                                 eframe::egui::Direction::LeftToRight,
                             ),
                             |ui| {
-                                if ui.button("Save").clicked() && self.save_name != "" {
-                                    self.close_save = true;
-                                    conf.save(&self.save_name, log);
-                                    log.push_str(&format!(
-                                        "Config file {} was saved.\n",
-                                        self.save_name
-                                    ));
+                                let label = if self.overwrite { "Overwrite" } else { "Save" };
+                                if ui.button(label).clicked() {
+                                    if let Err(e) = config::Config::check_name(&self.save_edit) {
+                                        log.push_str(&format!("{}\n", e));
+                                    } else if !self.overwrite
+                                        && config::Config::exists(&self.save_edit)
+                                    {
+                                        self.overwrite = true;
+                                    } else if ConfigDialog::save_as(
+                                        &self.save_edit,
+                                        conf,
+                                        plugin,
+                                        log,
+                                    ) {
+                                        self.save_name = self.save_edit.clone();
+                                        self.close_save = true;
+                                        saved = true;
+                                    }
                                 }
                             },
                         );
@@ -80,18 +405,147 @@ impl ConfigDialog {
                     eframe::egui::TopBottomPanel::top("save_top").show_inside(ui, |ui| {
                         ui.horizontal(|ui| {
                             ui.label("Save name: ");
-                            ui.add(eframe::egui::TextEdit::singleline(&mut self.save_name));
+                            if ui
+                                .add(eframe::egui::TextEdit::singleline(&mut self.save_edit))
+                                .changed()
+                            {
+                                self.overwrite = false;
+                            }
                         });
+                        if self.overwrite {
+                            ui.colored_label(
+                                eframe::egui::Color32::YELLOW,
+                                format!("Config {} already exists.", self.save_edit),
+                            );
+                        }
                     });
                 });
         }
+        if saved {
+            self.refresh(log);
+        }
+
+        if let Some(browser) = &mut self.export {
+            let mut open = true;
+            if let Some(path) = browser.show(ctx, &mut open) {
+                let bundle = browser.option().then(|| config::BundleInfo {
+                    plugin: conf.website.clone(),
+                    plugin_version: Some(plugin.version().to_owned()).filter(|v| {
+                        !v.is_empty() && conf.website.as_deref() == Some(plugin.name())
+                    }),
+                    exported: backup::timestamp(),
+                });
+                match conf.export(&path, bundle) {
+                    Ok(_) => {
+                        open = false;
+                        log.push_str(&format!("Config was exported to {}.\n", path.display()));
+                    }
+                    Err(e) => log.push_str(&format!("{}: {}\n", path.display(), e)),
+                }
+            }
+            if !open {
+                self.export = None;
+            }
+        }
+    }
+    // Makes loaded config current and starts its plugin
+    fn apply(
+        name: &str,
+        c: config::Config,
+        plugin: &mut plugin::Plugin,
+        log: &mut String,
+        lua_log: Rc<RefCell<String>>,
+    ) -> config::Config {
+        log.push_str(&format!("Config file {} was loaded.\n", name));
+        let settings = c.website.as_ref().and_then(|n| c.plugin_settings.get(n));
+        plugin.load(c.website.clone(), settings, log, lua_log);
+        plugin.config_loaded(name, log);
+        c
     }
-    pub fn show_open(&mut self, ctx: &eframe::egui::CtxRef, log: &mut String) -> Option<config::Config> {
+
+    pub fn show_open(
+        &mut self,
+        ctx: &eframe::egui::CtxRef,
+        plugin: &mut plugin::Plugin,
+        log: &mut String,
+        lua_log: Rc<RefCell<String>>,
+    ) -> Option<config::Config> {
         if self.close_open {
             self.close_open = false;
             self.open = false;
         }
         let mut conf: Option<config::Config> = None;
+        if let Some(invalid) = &self.invalid {
+            let mut decision = None;
+            eframe::egui::Window::new("Config has errors")
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    ui.label(format!("Config file {} has problems:", invalid.title));
+                    for i in &invalid.issues {
+                        ui.colored_label(eframe::egui::Color32::RED, format!("• {}", i));
+                    }
+                    ui.label("Fields with problems will be set to defaults.");
+                    ui.horizontal(|ui| {
+                        if ui.button("Open anyway with defaults").clicked() {
+                            decision = Some(true);
+                        }
+                        if ui.button("Cancel").clicked() {
+                            decision = Some(false);
+                        }
+                    });
+                });
+            match decision {
+                Some(true) => {
+                    if let Some(i) = self.invalid.take() {
+                        self.save_name = i.name;
+                        conf = Some(ConfigDialog::apply(
+                            &i.title,
+                            i.conf,
+                            plugin,
+                            log,
+                            lua_log.clone(),
+                        ));
+                        if let Some(b) = self.bundle.take() {
+                            ConfigDialog::check_bundle(&b, plugin, log);
+                        }
+                    }
+                }
+                Some(false) => {
+                    self.invalid = None;
+                    self.bundle = None;
+                }
+                None => (),
+            }
+        }
+        if let Some(browser) = &mut self.import {
+            let mut open = true;
+            if let Some(path) = browser.show(ctx, &mut open) {
+                open = false;
+                let (c, issues, bundle) = config::Config::import(&path);
+                let name = path
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                if issues.is_empty() {
+                    conf = Some(ConfigDialog::apply(&name, c, plugin, log, lua_log.clone()));
+                    // left untitled, so saving asks for a name and checks it
+                    self.save_name.clear();
+                    if let Some(b) = bundle {
+                        ConfigDialog::check_bundle(&b, plugin, log);
+                    }
+                } else {
+                    self.invalid = Some(Invalid {
+                        name: String::new(),
+                        ..Invalid::new(&name, c, issues)
+                    });
+                    self.bundle = bundle;
+                }
+            }
+            if !open {
+                self.import = None;
+            }
+        }
+        let mut changed = false;
         if self.open {
             eframe::egui::Window::new("Open config file")
                 .open(&mut self.open)
@@ -105,14 +559,24 @@ impl ConfigDialog {
                                 if ui.button("Open").clicked() {
                                     if let ConfigSelect::File(_) = self.file {
                                         self.close_open = true;
-                                        if let ConfigSelect::File(f) = self.file.clone() {
-                                            self.save_name = f;
+                                        let name = match &self.file {
+                                            ConfigSelect::File(f) => f.clone(),
+                                            ConfigSelect::None => String::new(),
+                                        };
+                                        let (c, issues) = config::Config::read(&name, log);
+                                        self.bundle = None;
+                                        if issues.is_empty() {
+                                            conf = Some(ConfigDialog::apply(
+                                                &name,
+                                                c,
+                                                plugin,
+                                                log,
+                                                lua_log.clone(),
+                                            ));
+                                            self.save_name = name;
+                                        } else {
+                                            self.invalid = Some(Invalid::new(&name, c, issues));
                                         }
-                                        conf = Some(config::Config::load(&self.save_name, log));
-                                        log.push_str(&format!(
-                                            "Config file {} was loaded.\n",
-                                            self.save_name
-                                        ));
                                     }
                                 }
                             },
@@ -127,7 +591,7 @@ impl ConfigDialog {
                                 "None".to_owned()
                             };
                             eframe::egui::ComboBox::from_id_source("select config")
-                                .selected_text(format!("{}", fname))
+                                .selected_text(fname.to_string())
                                 .show_ui(ui, |ui| {
                                     for i in 0..self.files.len() {
                                         let fname = if let ConfigSelect::File(fname) =
@@ -153,9 +617,708 @@ impl ConfigDialog {
                                     }
                                 });
                         });
+                        let selected = match &self.file {
+                            ConfigSelect::File(f) => Some(f.clone()),
+                            ConfigSelect::None => None,
+                        };
+                        ui.horizontal(|ui| {
+                            if ui.button("Refresh").clicked() {
+                                changed = true;
+                            }
+                            let enabled = selected.is_some();
+                            for (action, label) in [
+                                (FileAction::Rename, "Rename"),
+                                (FileAction::Duplicate, "Duplicate"),
+                                (FileAction::Delete, "Delete"),
+                                (FileAction::Restore, "Restore previous version"),
+                            ] {
+                                if ui
+                                    .add_enabled(enabled, eframe::egui::Button::new(label))
+                                    .clicked()
+                                {
+                                    self.action = Some(action);
+                                    self.backups = match (action, &selected) {
+                                        (FileAction::Restore, Some(f)) => {
+                                            config::Config::backups(f)
+                                        }
+                                        _ => Vec::new(),
+                                    };
+                                    self.restore_sel = None;
+                                    self.action_name = match action {
+                                        FileAction::Duplicate => {
+                                            format!("{} copy", selected.clone().unwrap_or_default())
+                                        }
+                                        _ => selected.clone().unwrap_or_default(),
+                                    };
+                                }
+                            }
+                        });
+                        if let (Some(action), Some(from)) = (self.action, selected) {
+                            let mut confirmed = false;
+                            ui.horizontal(|ui| {
+                                if action == FileAction::Delete {
+                                    ui.label(format!(
+                                        "Delete config {} and its saved versions?",
+                                        from
+                                    ));
+                                    confirmed = ui.button("Delete").clicked();
+                                } else if action == FileAction::Restore {
+                                    confirmed = ui
+                                        .add_enabled(
+                                            self.restore_sel.is_some(),
+                                            eframe::egui::Button::new("Restore"),
+                                        )
+                                        .clicked();
+                                } else {
+                                    ui.label("New name: ");
+                                    ui.text_edit_singleline(&mut self.action_name);
+                                    confirmed = ui.button("OK").clicked();
+                                }
+                                if ui.button("Cancel").clicked() {
+                                    self.action = None;
+                                }
+                            });
+                            if action == FileAction::Restore {
+                                if self.backups.is_empty() {
+                                    ui.label("No previous versions.");
+                                }
+                                for (i, b) in self.backups.iter().enumerate() {
+                                    ui.radio_value(
+                                        &mut self.restore_sel,
+                                        Some(i),
+                                        backup::pretty(&b.timestamp),
+                                    );
+                                }
+                            }
+                            if confirmed {
+                                match ConfigDialog::run_action(
+                                    action,
+                                    &from,
+                                    self.action_name.trim(),
+                                    self.restore_sel.and_then(|i| self.backups.get(i)),
+                                    log,
+                                ) {
+                                    Ok(sel) => {
+                                        if action == FileAction::Rename && self.save_name == from {
+                                            self.save_name = self.action_name.trim().to_owned();
+                                        }
+                                        self.file = sel;
+                                        self.action = None;
+                                        changed = true;
+                                    }
+                                    Err(e) => log.push_str(&format!("{}\n", e)),
+                                }
+                            }
+                        }
                     });
                 });
         }
+        if changed {
+            self.refresh(log);
+        }
         conf
     }
 }
+
+// What profile window asks the app to do
+pub enum ProfileAction {
+    Switch(String),
+    // Save current state under name
+    Save(String),
+}
+
+#[derive(Default)]
+pub struct ProfileDialog {
+    open: bool,
+    profiles: Vec<String>,
+    selected: Option<String>,
+    name: String,
+    // Selected profile waits for delete confirmation
+    delete: bool,
+    import: Option<FileBrowser>,
+    export: Option<FileBrowser>,
+}
+
+impl ProfileDialog {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn refresh(&mut self, log: &mut String) {
+        self.profiles = profile::Profile::list(log);
+        if !self
+            .selected
+            .as_ref()
+            .is_some_and(|s| self.profiles.contains(s))
+        {
+            self.selected = None;
+        }
+    }
+
+    pub fn profiles(&self) -> &[String] {
+        &self.profiles
+    }
+
+    pub fn open_dialog(&mut self, log: &mut String) {
+        self.refresh(log);
+        self.delete = false;
+        self.open = true;
+    }
+
+    pub fn show(&mut self, ctx: &eframe::egui::CtxRef, log: &mut String) -> Option<ProfileAction> {
+        let mut action = None;
+        let mut changed = false;
+        if let Some(browser) = &mut self.import {
+            let mut open = true;
+            if let Some(path) = browser.show(ctx, &mut open) {
+                match profile::Profile::import(&path, log) {
+                    Ok(name) => {
+                        open = false;
+                        log.push_str(&format!("Profile {} was imported.\n", name));
+                        self.selected = Some(name);
+                        changed = true;
+                    }
+                    Err(e) => log.push_str(&format!("{}: {}\n", path.display(), e)),
+                }
+            }
+            if !open {
+                self.import = None;
+            }
+        }
+        if let (Some(browser), Some(name)) = (&mut self.export, &self.selected) {
+            let mut open = true;
+            if let Some(path) = browser.show(ctx, &mut open) {
+                match profile::Profile::export(name, &path) {
+                    Ok(_) => {
+                        open = false;
+                        log.push_str(&format!(
+                            "Profile {} was exported to {}.\n",
+                            name,
+                            path.display()
+                        ));
+                    }
+                    Err(e) => log.push_str(&format!("{}: {}\n", path.display(), e)),
+                }
+            }
+            if !open {
+                self.export = None;
+            }
+        }
+        if !self.open {
+            return None;
+        }
+        eframe::egui::Window::new("Profiles")
+            .open(&mut self.open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Name: ");
+                    ui.text_edit_singleline(&mut self.name);
+                    if ui.button("Save current").clicked() {
+                        let name = self.name.trim();
+                        match config::Config::check_name(name) {
+                            Ok(_) => {
+                                action = Some(ProfileAction::Save(name.to_owned()));
+                                self.selected = Some(name.to_owned());
+                            }
+                            Err(e) => log.push_str(&format!("{}\n", e)),
+                        }
+                    }
+                });
+                eframe::egui::ScrollArea::vertical()
+                    .max_height(150.)
+                    .show(ui, |ui| {
+                        for p in &self.profiles {
+                            let selected = self.selected.as_ref() == Some(p);
+                            if ui.selectable_label(selected, p).clicked() {
+                                self.selected = Some(p.clone());
+                                self.name = p.clone();
+                                self.delete = false;
+                            }
+                        }
+                    });
+                let enabled = self.selected.is_some();
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(enabled, eframe::egui::Button::new("Switch"))
+                        .clicked()
+                    {
+                        action = self.selected.clone().map(ProfileAction::Switch);
+                    }
+                    if ui
+                        .add_enabled(enabled, eframe::egui::Button::new("Delete"))
+                        .clicked()
+                    {
+                        self.delete = true;
+                    }
+                    if ui
+                        .add_enabled(enabled, eframe::egui::Button::new("Export..."))
+                        .clicked()
+                    {
+                        let name = self.selected.clone().unwrap_or_default();
+                        self.export = Some(FileBrowser::new(
+                            "Export profile",
+                            &format!("{}.zip", name),
+                            profile::EXTENSIONS,
+                        ));
+                    }
+                    if ui.button("Import...").clicked() {
+                        self.import =
+                            Some(FileBrowser::new("Import profile", "", profile::EXTENSIONS));
+                    }
+                });
+                if let (true, Some(name)) = (self.delete, &self.selected) {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("Delete profile {}?", name));
+                        if ui.button("Delete").clicked() {
+                            match profile::Profile::delete(name) {
+                                Ok(_) => log.push_str(&format!("Profile {} was deleted.\n", name)),
+                                Err(e) => log.push_str(&format!("{}: {}\n", name, e)),
+                            }
+                            self.delete = false;
+                            changed = true;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.delete = false;
+                        }
+                    });
+                }
+            });
+        if changed {
+            self.refresh(log);
+        }
+        action
+    }
+}
+
+// Unpacking of bundled plugins, asks what to do with locally modified files
+#[derive(Default)]
+pub struct UnpackDialog {
+    open: bool,
+    plans: Vec<unpack::PluginPlan>,
+    // Lines describing finished unpack
+    summary: Option<Vec<String>>,
+}
+
+impl UnpackDialog {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn open_dialog(&mut self, log: &mut String) {
+        self.plans = unpack::plan(&paths::get().plugins(), log);
+        self.summary = None;
+        self.open = true;
+    }
+
+    pub fn show(&mut self, ctx: &eframe::egui::CtxRef, log: &mut String) {
+        if !self.open {
+            return;
+        }
+        let mut unpack = false;
+        let mut close = false;
+        eframe::egui::Window::new("Unpack plugins")
+            .open(&mut self.open)
+            .show(ctx, |ui| {
+                if let Some(summary) = &self.summary {
+                    for line in summary {
+                        ui.label(line);
+                    }
+                    close = ui.button("Close").clicked();
+                    return;
+                }
+                for p in &mut self.plans {
+                    let status = match &p.installed {
+                        None => "not installed".to_owned(),
+                        Some(v) if p.skip => format!("installed {} is newer, skipped", v),
+                        Some(v) => format!("installed {}", v),
+                    };
+                    ui.label(format!("{} {}: {}", p.name, p.bundled, status));
+                    if p.skip || p.conflicts().next().is_none() {
+                        continue;
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label("All modified files:");
+                        for r in unpack::Resolve::ALL {
+                            if ui.small_button(r.label()).clicked() {
+                                for f in &mut p.files {
+                                    f.resolve = r;
+                                }
+                            }
+                        }
+                    });
+                    for f in p
+                        .files
+                        .iter_mut()
+                        .filter(|f| f.state == unpack::FileState::Modified)
+                    {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("  {} (modified)", f.path));
+                            for r in unpack::Resolve::ALL {
+                                ui.radio_value(&mut f.resolve, r, r.label());
+                            }
+                        });
+                    }
+                }
+                ui.horizontal(|ui| {
+                    unpack = ui.button("Unpack").clicked();
+                    close = ui.button("Cancel").clicked();
+                });
+            });
+        if unpack {
+            let path = paths::get().plugins();
+            let summary = unpack::apply(&path, &self.plans, log);
+            for line in &summary {
+                log.push_str(&format!("{}\n", line));
+            }
+            self.summary = Some(summary);
+        }
+        if close {
+            self.open = false;
+        }
+    }
+}
+
+pub enum PluginChange {
+    Installed(String),
+    Uninstalled(String),
+}
+
+// Installing, updating and removing plugins
+#[derive(Default)]
+pub struct InstallDialog {
+    browser: Option<FileBrowser>,
+    update: bool,
+    // Plugin waiting for uninstall confirmation
+    uninstall: Option<String>,
+}
+
+impl InstallDialog {
+    pub fn install_dialog(&mut self, update: bool) {
+        let title = if update {
+            "Update plugin from archive"
+        } else {
+            "Install plugin from archive"
+        };
+        self.update = update;
+        self.browser = Some(FileBrowser::new(title, "", install::EXTENSIONS));
+    }
+
+    pub fn uninstall_dialog(&mut self, name: String) {
+        self.uninstall = Some(name);
+    }
+
+    pub fn show(&mut self, ctx: &eframe::egui::CtxRef, log: &mut String) -> Option<PluginChange> {
+        let mut change = None;
+        if let Some(browser) = &mut self.browser {
+            let mut open = true;
+            if let Some(path) = browser.show(ctx, &mut open) {
+                match install::install(&path, self.update, log) {
+                    Ok(name) => {
+                        open = false;
+                        change = Some(PluginChange::Installed(name));
+                    }
+                    Err(e) => log.push_str(&format!("{}: {}\n", path.display(), e)),
+                }
+            }
+            if !open {
+                self.browser = None;
+            }
+        }
+        if let Some(name) = self.uninstall.clone() {
+            let mut decision = None;
+            eframe::egui::Window::new("Uninstall plugin")
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    ui.label(format!(
+                        "Remove plugin {}? Data it has written is kept.",
+                        name
+                    ));
+                    ui.horizontal(|ui| {
+                        if ui.button("Uninstall").clicked() {
+                            decision = Some(true);
+                        }
+                        if ui.button("Cancel").clicked() {
+                            decision = Some(false);
+                        }
+                    });
+                });
+            if let Some(remove) = decision {
+                self.uninstall = None;
+                if remove {
+                    match install::uninstall(&name) {
+                        Ok(_) => {
+                            log.push_str(&format!("Plugin {} was uninstalled.\n", name));
+                            change = Some(PluginChange::Uninstalled(name));
+                        }
+                        Err(e) => log.push_str(&format!("{}\n", e)),
+                    }
+                }
+            }
+        }
+        change
+    }
+}
+
+type BrowserEntries = Vec<(repo::Entry, Option<String>)>;
+
+// Result of background repository work
+struct BrowserTask {
+    log: String,
+    installed: Option<String>,
+    entries: Result<BrowserEntries, String>,
+}
+
+// Plugins offered by repository with their installed versions
+#[derive(Default)]
+pub struct PluginBrowser {
+    open: bool,
+    location: String,
+    entries: BrowserEntries,
+    error: Option<String>,
+    task: Option<std::sync::mpsc::Receiver<BrowserTask>>,
+}
+
+impl PluginBrowser {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn open_dialog(&mut self, settings: &Settings) {
+        self.location = settings.repository.clone();
+        self.start(None);
+        self.open = true;
+    }
+
+    // Network and disk work runs on a worker thread, so the window stays
+    // responsive. Optionally installs entry first, then reloads index.
+    fn start(&mut self, install: Option<repo::Entry>) {
+        let location = self.location.clone();
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let mut log = String::new();
+            let mut installed = None;
+            if let Some(entry) = install {
+                match repo::Source::parse(&location)
+                    .and_then(|s| repo::install(&s, &entry, &mut log))
+                {
+                    Ok(name) => installed = Some(name),
+                    Err(e) => log.push_str(&format!("{}: {}\n", entry.name, e)),
+                }
+            }
+            let entries = repo::Source::parse(&location)
+                .and_then(|s| s.index())
+                .map(|entries| {
+                    entries
+                        .into_iter()
+                        .map(|e| {
+                            let installed = plugin::installed_version(&e.name);
+                            (e, installed)
+                        })
+                        .collect()
+                });
+            let _ = tx.send(BrowserTask {
+                log,
+                installed,
+                entries,
+            });
+        });
+        self.task = Some(rx);
+    }
+
+    // Takes result of finished task, if any
+    fn poll(&mut self, log: &mut String) -> Option<PluginChange> {
+        let res = match self.task.as_ref()?.try_recv() {
+            Ok(res) => res,
+            Err(std::sync::mpsc::TryRecvError::Empty) => return None,
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                self.task = None;
+                self.error = Some("repository task failed".to_owned());
+                return None;
+            }
+        };
+        self.task = None;
+        log.push_str(&res.log);
+        match res.entries {
+            Ok(entries) => {
+                self.entries = entries;
+                self.error = None;
+            }
+            Err(e) => {
+                self.entries.clear();
+                self.error = Some(e);
+            }
+        }
+        res.installed.map(PluginChange::Installed)
+    }
+
+    pub fn show(
+        &mut self,
+        ctx: &eframe::egui::CtxRef,
+        settings: &mut Settings,
+        log: &mut String,
+    ) -> Option<PluginChange> {
+        // finished work is picked up even if window was closed meanwhile
+        let change = self.poll(log);
+        let busy = self.task.is_some();
+        if busy {
+            ctx.request_repaint();
+        }
+        if !self.open {
+            return change;
+        }
+        let mut refresh = false;
+        let mut chosen = None;
+        let entries = &self.entries;
+        let location = &mut self.location;
+        let error = &self.error;
+        eframe::egui::Window::new("Plugin browser")
+            .open(&mut self.open)
+            .show(ctx, |ui| {
+                ui.set_enabled(!busy);
+                ui.horizontal(|ui| {
+                    ui.label("Repository: ");
+                    ui.text_edit_singleline(location);
+                    refresh = ui.button("Refresh").clicked();
+                });
+                if busy {
+                    ui.label("Working...");
+                } else if let Some(e) = error {
+                    ui.colored_label(eframe::egui::Color32::RED, e);
+                }
+                eframe::egui::Grid::new("plugin_browser")
+                    .num_columns(5)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for (i, (e, installed)) in entries.iter().enumerate() {
+                            let newer = match installed {
+                                Some(v) => match (Version::parse(&e.version), Version::parse(v)) {
+                                    (Ok(a), Ok(b)) => a > b,
+                                    _ => e.version != *v,
+                                },
+                                None => false,
+                            };
+                            ui.label(&e.name);
+                            ui.label(&e.version);
+                            ui.horizontal(|ui| {
+                                ui.label(installed.as_deref().unwrap_or("—"));
+                                if newer {
+                                    ui.colored_label(eframe::egui::Color32::GOLD, "update");
+                                }
+                            });
+                            ui.label(&e.description);
+                            let action = match installed {
+                                None => Some("Install"),
+                                Some(_) if newer => Some("Update"),
+                                Some(_) => None,
+                            };
+                            if let Some(a) = action {
+                                if ui.button(a).clicked() {
+                                    chosen = Some(i);
+                                }
+                            } else {
+                                ui.label("");
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+        if busy || !(refresh || chosen.is_some()) {
+            return change;
+        }
+        if settings.repository != self.location {
+            settings.repository = self.location.clone();
+            if let Err(e) = settings.save() {
+                log.push_str(&format!("{}\n", e));
+            }
+        }
+        self.start(chosen.map(|i| self.entries[i].0.clone()));
+        change
+    }
+}
+
+// Manifest fields and state of loaded plugin.
+// Returns true when its documentation should be opened.
+pub fn plugin_info(ui: &mut eframe::egui::Ui, plugin: &plugin::Plugin) -> bool {
+    let mut open_doc = false;
+    let info = plugin.info().cloned().unwrap_or_default();
+    eframe::egui::Grid::new("plugin_info")
+        .num_columns(2)
+        .show(ui, |ui| {
+            let row = |ui: &mut eframe::egui::Ui, label: &str, value: &str| {
+                if !value.is_empty() {
+                    ui.label(label);
+                    ui.label(value);
+                    ui.end_row();
+                }
+            };
+            row(ui, "Name", &info.name);
+            row(ui, "Version", &info.version);
+            row(ui, "Author", &info.author);
+            row(ui, "Description", &info.description);
+            row(ui, "Minimum host version", &info.min_host_version);
+            row(ui, "Supported API", &info.api_version);
+            row(ui, "Host API", plugin::API_VERSION);
+            if !info.homepage.is_empty() {
+                ui.label("Documentation");
+                if ui
+                    .button(&info.homepage)
+                    .on_hover_text(plugin.dir().join(&info.homepage).display().to_string())
+                    .clicked()
+                {
+                    open_doc = true;
+                }
+                ui.end_row();
+            }
+            row(ui, "Status", plugin.load_status());
+            if let Some(status) = plugin.integrity() {
+                row(ui, "Integrity", &status.label());
+            }
+            let granted: Vec<&str> = plugin.granted().iter().map(|c| c.name()).collect();
+            ui.label("Permissions");
+            if granted.is_empty() {
+                ui.label("none");
+            } else {
+                ui.label(granted.join(", "));
+            }
+            ui.end_row();
+            if let Some(e) = plugin.last_error() {
+                ui.label("Last error");
+                ui.colored_label(eframe::egui::Color32::RED, e);
+                ui.end_row();
+            }
+        });
+    open_doc
+}
+
+// Documentation file of plugin shown as plain text
+#[derive(Default)]
+pub struct PluginDoc {
+    open: bool,
+    title: String,
+    text: String,
+}
+
+impl PluginDoc {
+    pub fn open_doc(&mut self, plugin: &plugin::Plugin) {
+        let info = plugin.info().cloned().unwrap_or_default();
+        self.title = format!("{} documentation", info.name);
+        self.text = info
+            .doc_path(plugin.dir())
+            .and_then(|path| {
+                std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))
+            })
+            .unwrap_or_else(|e| e);
+        self.open = true;
+    }
+
+    pub fn show(&mut self, ctx: &eframe::egui::CtxRef) {
+        eframe::egui::Window::new(&self.title)
+            .id(eframe::egui::Id::new("plugin_doc"))
+            .open(&mut self.open)
+            .show(ctx, |ui| {
+                eframe::egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.label(&self.text);
+                });
+            });
+    }
+}
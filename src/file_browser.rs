@@ -0,0 +1,127 @@
+use std::path::{Path, PathBuf};
+
+use eframe::egui;
+
+// Window for picking a file anywhere on disk
+pub struct FileBrowser {
+    title: &'static str,
+    dir: PathBuf,
+    dir_text: String,
+    name: String,
+    extensions: &'static [&'static str],
+    // (name, is directory), directories first
+    entries: Vec<(String, bool)>,
+    error: Option<String>,
+    // Extra checkbox shown under file name
+    option: Option<(&'static str, bool)>,
+}
+
+impl FileBrowser {
+    pub fn new(title: &'static str, name: &str, extensions: &'static [&'static str]) -> Self {
+        let dir = dirs::home_dir()
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default();
+        let mut b = Self {
+            title,
+            dir: PathBuf::new(),
+            dir_text: String::new(),
+            name: name.to_owned(),
+            extensions,
+            entries: Vec::new(),
+            error: None,
+            option: None,
+        };
+        b.go(dir);
+        b
+    }
+
+    pub fn with_option(mut self, label: &'static str, value: bool) -> Self {
+        self.option = Some((label, value));
+        self
+    }
+
+    pub fn option(&self) -> bool {
+        self.option.is_some_and(|(_, v)| v)
+    }
+
+    fn go(&mut self, dir: PathBuf) {
+        match dir.read_dir() {
+            Ok(rd) => {
+                let mut entries: Vec<(String, bool)> = rd
+                    .flatten()
+                    .filter_map(|e| {
+                        let name = e.file_name().to_string_lossy().into_owned();
+                        let is_dir = e.path().is_dir();
+                        let shown = !name.starts_with('.') && (is_dir || self.matches(&e.path()));
+                        shown.then_some((name, is_dir))
+                    })
+                    .collect();
+                entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+                self.entries = entries;
+                self.dir_text = dir.display().to_string();
+                self.dir = dir;
+                self.error = None;
+            }
+            Err(e) => {
+                self.dir_text = self.dir.display().to_string();
+                self.error = Some(format!("{}: {}", dir.display(), e));
+            }
+        }
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        path.extension()
+            .is_some_and(|e| self.extensions.iter().any(|x| e == *x))
+    }
+
+    // Returns chosen path once user confirms, `open` is cleared on close
+    pub fn show(&mut self, ctx: &egui::CtxRef, open: &mut bool) -> Option<PathBuf> {
+        let mut chosen = None;
+        let mut go_to = None;
+        egui::Window::new(self.title).open(open).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Up").clicked() {
+                    go_to = self.dir.parent().map(Path::to_path_buf);
+                }
+                let r = ui.text_edit_singleline(&mut self.dir_text);
+                if r.lost_focus() && ui.input().key_pressed(egui::Key::Enter) {
+                    go_to = Some(PathBuf::from(&self.dir_text));
+                }
+            });
+            egui::ScrollArea::vertical()
+                .max_height(200.)
+                .show(ui, |ui| {
+                    for (name, is_dir) in &self.entries {
+                        if *is_dir {
+                            if ui.selectable_label(false, format!("{}/", name)).clicked() {
+                                go_to = Some(self.dir.join(name));
+                            }
+                        } else if ui.selectable_label(self.name == *name, name).clicked() {
+                            self.name = name.clone();
+                        }
+                    }
+                });
+            if let Some(e) = &self.error {
+                ui.colored_label(egui::Color32::RED, e);
+            }
+            ui.horizontal(|ui| {
+                ui.label("File name: ");
+                ui.text_edit_singleline(&mut self.name);
+                if ui
+                    .add_enabled(!self.name.is_empty(), egui::Button::new("OK"))
+                    .clicked()
+                {
+                    chosen = Some(self.dir.join(&self.name));
+                }
+            });
+            if let Some((label, value)) = &mut self.option {
+                ui.checkbox(value, *label);
+            }
+            ui.label(format!("Formats: {}", self.extensions.join(", ")));
+        });
+        if let Some(dir) = go_to {
+            self.go(dir);
+        }
+        chosen
+    }
+}
@@ -0,0 +1,368 @@
+use eframe::egui::{self, Ui};
+use toml::{value::Table, Value};
+
+use crate::schema::Shape;
+
+// What can't be read from types: ranges of numbers, fields whose value is
+// picked from another list field, and fields edited elsewhere
+pub struct Hints {
+    pub ranges: &'static [(&'static str, i64, i64)],
+    pub choices: &'static [(&'static str, &'static str)],
+    pub skip: &'static [&'static str],
+}
+
+// "pas_len" -> "Pas len"
+fn label(field: &str) -> String {
+    let mut s = field.replace('_', " ");
+    if let Some(c) = s.get(0..1) {
+        let upper = c.to_uppercase();
+        s.replace_range(0..1, &upper);
+    }
+    s
+}
+
+fn default_value(shape: &Shape) -> Option<Value> {
+    match shape {
+        Shape::Bool => Some(Value::Boolean(false)),
+        Shape::Int { min, .. } => Some(Value::Integer((*min).max(0))),
+        Shape::Float => Some(Value::Float(0.)),
+        Shape::Str => Some(Value::String(String::new())),
+        Shape::Enum(variants) => variants.first().and_then(|v| variant_value(v.0, &v.1)),
+        Shape::Seq(_) => Some(Value::Array(Vec::new())),
+        _ => None,
+    }
+}
+
+// Unit variant is its name, variant with value is `{ Name = value }`
+fn variant_value(name: &str, value: &Option<Shape>) -> Option<Value> {
+    match value {
+        None => Some(Value::String(name.to_owned())),
+        Some(shape) => {
+            let mut t = Table::new();
+            t.insert(name.to_owned(), default_value(shape)?);
+            Some(Value::Table(t))
+        }
+    }
+}
+
+fn variant_name(v: &Value) -> Option<&str> {
+    match v {
+        Value::String(s) => Some(s),
+        Value::Table(t) if t.len() == 1 => t.keys().next().map(String::as_str),
+        _ => None,
+    }
+}
+
+// Shows editors for struct fields of table. Returns names of changed fields.
+pub fn show(ui: &mut Ui, shape: &Shape, table: &mut Table, hints: &Hints) -> Vec<String> {
+    let fields = match shape {
+        Shape::Struct(f) => f,
+        _ => return Vec::new(),
+    };
+    let mut changed = Vec::new();
+    egui::Grid::new("config_form")
+        .num_columns(2)
+        .striped(true)
+        .show(ui, |ui| {
+            for (name, shape) in fields {
+                if hints.skip.contains(name) {
+                    continue;
+                }
+                ui.label(label(name));
+                let mut value = table.get(*name).cloned();
+                let choices = hints
+                    .choices
+                    .iter()
+                    .find(|c| c.0 == *name)
+                    .and_then(|c| table.get(c.1))
+                    .and_then(Value::as_array)
+                    .cloned();
+                let edited = match (&choices, &mut value) {
+                    (Some(list), Some(v)) => choose(ui, name, list, v),
+                    _ => {
+                        let range = hints
+                            .ranges
+                            .iter()
+                            .find(|r| r.0 == *name)
+                            .map(|r| (r.1, r.2));
+                        edit(ui, name, shape, &mut value, range)
+                    }
+                };
+                if edited {
+                    match value {
+                        Some(v) => table.insert(name.to_string(), v),
+                        None => table.remove(*name),
+                    };
+                    changed.push(name.to_string());
+                    changed.extend(clamp_choices(fields, table, hints, name));
+                }
+                ui.end_row();
+            }
+        });
+    changed
+}
+
+// Keeps values picked from list field `list` inside it after the list was edited.
+// A choice whose value was removed falls back to the first item. List emptied
+// completely gets the default value back, so there is always something to pick.
+fn clamp_choices(
+    fields: &[(&str, Shape)],
+    table: &mut Table,
+    hints: &Hints,
+    list: &str,
+) -> Vec<String> {
+    let mut changed = Vec::new();
+    for (field, _) in hints.choices.iter().filter(|c| c.1 == list) {
+        let selected = match table.get(*field) {
+            Some(v) => v.clone(),
+            None => continue,
+        };
+        let items = match table.get_mut(list) {
+            Some(Value::Array(items)) => items,
+            _ => continue,
+        };
+        if items.contains(&selected) {
+            continue;
+        }
+        if items.is_empty() {
+            let default = fields
+                .iter()
+                .find(|f| f.0 == *field)
+                .and_then(|f| default_value(&f.1));
+            match default {
+                Some(v) => items.push(v),
+                None => continue,
+            }
+            changed.push(list.to_owned());
+        }
+        let first = items[0].clone();
+        table.insert(field.to_string(), first);
+        changed.push(field.to_string());
+    }
+    changed
+}
+
+// Combo box over values of another field
+fn choose(ui: &mut Ui, id: &str, list: &[Value], value: &mut Value) -> bool {
+    let text = |v: &Value| match v {
+        Value::String(s) if s.is_empty() => "None".to_owned(),
+        Value::String(s) => s.to_owned(),
+        // variant with value, like `{ File = "path" }`
+        Value::Table(t) if t.len() == 1 => match t.values().next() {
+            Some(Value::String(s)) => s.to_owned(),
+            _ => v.to_string(),
+        },
+        _ => v.to_string(),
+    };
+    let mut changed = false;
+    egui::ComboBox::from_id_source(id)
+        .selected_text(text(value))
+        .show_ui(ui, |ui| {
+            for item in list {
+                changed |= ui.selectable_label(item == value, text(item)).clicked();
+                if changed && item != value {
+                    *value = item.clone();
+                }
+            }
+        });
+    changed
+}
+
+fn edit(
+    ui: &mut Ui,
+    id: &str,
+    shape: &Shape,
+    value: &mut Option<Value>,
+    range: Option<(i64, i64)>,
+) -> bool {
+    if let Shape::Option(inner) = shape {
+        return ui
+            .horizontal(|ui| {
+                let mut set = value.is_some();
+                let mut changed = ui.checkbox(&mut set, "").changed();
+                if changed {
+                    *value = if set { default_value(inner) } else { None };
+                }
+                if let Some(v) = value {
+                    changed |= edit_value(ui, id, inner, v, range);
+                }
+                changed
+            })
+            .inner;
+    }
+    if value.is_none() {
+        *value = default_value(shape);
+    }
+    match value {
+        Some(v) => edit_value(ui, id, shape, v, range),
+        None => {
+            ui.label("—");
+            false
+        }
+    }
+}
+
+fn edit_value(
+    ui: &mut Ui,
+    id: &str,
+    shape: &Shape,
+    v: &mut Value,
+    range: Option<(i64, i64)>,
+) -> bool {
+    match (shape, v) {
+        (Shape::Bool, Value::Boolean(b)) => ui.checkbox(b, "").changed(),
+        (Shape::Int { min, max }, Value::Integer(i)) => {
+            let (lo, hi) = range.unwrap_or((*min, *max));
+            ui.add(egui::DragValue::new(i).clamp_range(lo..=hi))
+                .changed()
+        }
+        (Shape::Float, Value::Float(f)) => ui.add(egui::DragValue::new(f)).changed(),
+        (Shape::Str, Value::String(s)) => ui.text_edit_singleline(s).changed(),
+        (Shape::Enum(variants), v) if variant_name(v).is_some() => {
+            ui.horizontal(|ui| {
+                let current = variant_name(v).unwrap_or_default().to_owned();
+                let mut changed = false;
+                egui::ComboBox::from_id_source(id)
+                    .selected_text(&current)
+                    .show_ui(ui, |ui| {
+                        for (name, value) in variants {
+                            if ui.selectable_label(current == *name, *name).clicked()
+                                && current != *name
+                            {
+                                if let Some(new) = variant_value(name, value) {
+                                    *v = new;
+                                    changed = true;
+                                }
+                            }
+                        }
+                    });
+                // value of the variant, like path of `File`
+                let shape = variants.iter().find(|(n, _)| *n == current);
+                if let (Some((_, Some(shape))), Value::Table(t)) = (shape, v) {
+                    if let Some(inner) = t.get_mut(&current) {
+                        changed |= edit_value(ui, &format!("{}/value", id), shape, inner, range);
+                    }
+                }
+                changed
+            })
+            .inner
+        }
+        (Shape::Seq(inner), Value::Array(items)) => {
+            ui.vertical(|ui| {
+                let mut changed = false;
+                let mut remove = None;
+                for (i, item) in items.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        changed |= edit_value(ui, &format!("{}/{}", id, i), inner, item, range);
+                        if ui.small_button("-").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = remove {
+                    items.remove(i);
+                    changed = true;
+                }
+                if ui.small_button("+").clicked() {
+                    if let Some(v) = default_value(inner) {
+                        items.push(v);
+                        changed = true;
+                    }
+                }
+                changed
+            })
+            .inner
+        }
+        (_, v) => {
+            ui.label(v.to_string());
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HINTS: Hints = Hints {
+        ranges: &[],
+        choices: &[("sel", "list")],
+        skip: &[],
+    };
+
+    fn file_choice() -> Shape {
+        Shape::Enum(vec![("None", None), ("File", Some(Shape::Str))])
+    }
+
+    fn fields() -> Vec<(&'static str, Shape)> {
+        vec![
+            ("list", Shape::Seq(Box::new(file_choice()))),
+            ("sel", file_choice()),
+        ]
+    }
+
+    fn table(list: &[&str], sel: &str) -> Table {
+        let mut t = Table::new();
+        t.insert(
+            "list".to_owned(),
+            Value::Array(list.iter().map(|s| Value::String(s.to_string())).collect()),
+        );
+        t.insert("sel".to_owned(), Value::String(sel.to_owned()));
+        t
+    }
+
+    #[test]
+    fn keeps_listed_choice() {
+        let mut t = table(&["a", "b"], "b");
+        assert!(clamp_choices(&fields(), &mut t, &HINTS, "list").is_empty());
+        assert_eq!(t["sel"].as_str(), Some("b"));
+    }
+
+    #[test]
+    fn clamps_removed_choice() {
+        let mut t = table(&["a"], "b");
+        assert_eq!(
+            clamp_choices(&fields(), &mut t, &HINTS, "list"),
+            vec!["sel"]
+        );
+        assert_eq!(t["sel"].as_str(), Some("a"));
+
+        let mut t = table(&[], "b");
+        assert_eq!(
+            clamp_choices(&fields(), &mut t, &HINTS, "list"),
+            vec!["list", "sel"]
+        );
+        assert_eq!(t["list"].as_array().map(Vec::len), Some(1));
+        assert_eq!(t["sel"].as_str(), Some("None"));
+    }
+
+    #[test]
+    fn file_variant_is_accepted_by_config() {
+        use crate::config::{Config, Proxy};
+
+        let fields = match crate::schema::trace::<Config>() {
+            Shape::Struct(f) => f,
+            s => panic!("{:?}", s),
+        };
+        let variants = match fields.iter().find(|f| f.0 == "proxy_sel") {
+            Some((_, Shape::Enum(v))) => v.clone(),
+            s => panic!("{:?}", s),
+        };
+        let mut file = variant_value("File", &variants[1].1).unwrap();
+        assert_eq!(variant_name(&file), Some("File"));
+        if let Value::Table(t) = &mut file {
+            t.insert("File".to_owned(), Value::String("p.txt".to_owned()));
+        }
+
+        let mut conf = Config::default();
+        let mut t = conf.to_table().unwrap();
+        if let Some(Value::Array(items)) = t.get_mut("proxy_files") {
+            items.push(file.clone());
+        }
+        t.insert("proxy_sel".to_owned(), file);
+        let fields = ["proxy_files".to_owned(), "proxy_sel".to_owned()];
+        conf.set_fields(&fields, &t).unwrap();
+        assert_eq!(conf.proxy_sel, Proxy::File("p.txt".to_owned()));
+        assert_eq!(conf.proxy_files.len(), 2);
+    }
+}
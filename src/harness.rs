@@ -0,0 +1,309 @@
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use mlua::Table;
+
+use crate::{
+    config, paths,
+    plugin::{self, Plugin},
+    plugin_ui::{Node, Recording, Target},
+    snapshot,
+};
+
+// Runs plugin without window: draw() output is recorded as widget tree
+pub struct Harness {
+    plugin: Plugin,
+    lua_log: Rc<RefCell<String>>,
+    recording: Rc<RefCell<Recording>>,
+    // Empty for every run and removed with the harness
+    data_dir: PathBuf,
+}
+
+// Numbers data directories of harnesses within one process
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.data_dir);
+    }
+}
+
+impl Harness {
+    // Loads plugin from directory granting everything it asks for
+    pub fn load(name: &str, dir: &Path) -> Result<Harness, String> {
+        let lua_log = Rc::new(RefCell::new(String::new()));
+        let mut log = String::new();
+        let mut plugin = Plugin::new();
+        let data_dir = std::env::temp_dir().join(format!(
+            "flexar-test-{}-{}-{}",
+            name,
+            std::process::id(),
+            RUNS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&data_dir);
+
+        plugin.load_dir(
+            name.to_owned(),
+            dir.to_path_buf(),
+            data_dir.clone(),
+            None,
+            &mut log,
+        );
+        // from here on the directory is removed on return
+        let mut h = Harness {
+            plugin,
+            lua_log,
+            recording: Rc::new(RefCell::new(Recording::default())),
+            data_dir,
+        };
+        if h.plugin.lua().is_none() {
+            return Err(log);
+        }
+        let granted = h.plugin.pending_permissions().unwrap_or(&[]).to_vec();
+        h.plugin.start(granted, &mut log, h.lua_log.clone());
+        if log.lines().any(|l| !l.starts_with(plugin::WARNING)) {
+            return Err(log);
+        }
+        eprint!("{}", log);
+        Ok(h)
+    }
+
+    // Calls draw() and returns recorded widgets
+    pub fn draw(&mut self) -> Result<Vec<Node>, String> {
+        self.recording = Rc::new(RefCell::new(Recording::default()));
+        let mut log = String::new();
+        let res = self.plugin.call_draw(
+            Target::Record(self.recording.clone()),
+            &self.lua_log,
+            &mut log,
+        );
+        let errors = std::mem::take(&mut self.recording.borrow_mut().errors);
+        if res.is_err() || !log.is_empty() || !errors.is_empty() {
+            return Err(format!("{}{}", log, errors.join("\n")));
+        }
+        Ok(self.recording.borrow().nodes.clone())
+    }
+
+    // Finds widget callback table recorded on the last draw
+    fn handler(&self, id: &str) -> Result<(&'static str, Table<'static>), String> {
+        let rec = self.recording.borrow();
+        let (kind, key) = match rec.handlers.get(id) {
+            Some(h) => h,
+            None => return Err(format!("widget '{}' not found", id)),
+        };
+        let table = self
+            .plugin
+            .lua()
+            .ok_or_else(|| "plugin isn't loaded".to_owned())?
+            .registry_value::<Table>(key)
+            .map_err(|e| e.to_string())?;
+        Ok((kind, table))
+    }
+
+    fn call_cb<A: mlua::ToLuaMulti<'static>>(table: Table<'static>, args: A) -> Result<(), String> {
+        match table.get::<&str, Option<mlua::Function>>("cb") {
+            Ok(Some(f)) => f.call(args).map_err(|e| e.to_string()),
+            Ok(None) => Ok(()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    pub fn click(&mut self, id: &str) -> Result<(), String> {
+        match self.handler(id)? {
+            ("button", table) => Harness::call_cb(table, ()),
+            (kind, _) => Err(format!("widget '{}' is {}, not a button", id, kind)),
+        }
+    }
+
+    // Changes input value: text for text_edit, "true"/"false" for checkbox
+    pub fn input(&mut self, id: &str, value: &str) -> Result<(), String> {
+        match self.handler(id)? {
+            ("text_edit", table) => Harness::call_cb(table, value.to_owned()),
+            ("checkbox", table) => match value.parse::<bool>() {
+                Ok(b) => Harness::call_cb(table, b),
+                Err(_) => Err(format!("checkbox '{}' expects true or false", id)),
+            },
+            (kind, _) => Err(format!("widget '{}' is {}, not an input", id, kind)),
+        }
+    }
+
+    // Text printed by plugin since last call
+    pub fn take_output(&mut self) -> String {
+        std::mem::take(&mut *self.lua_log.borrow_mut())
+    }
+}
+
+// Renders widget tree as indented text, one widget per line
+pub fn render_tree(nodes: &[Node]) -> String {
+    fn render(out: &mut String, nodes: &[Node], depth: usize) {
+        for n in nodes {
+            out.push_str(&"  ".repeat(depth));
+            out.push_str(&format!("{} #{} {:?}", n.kind, n.id, n.text));
+            if let Some(v) = &n.value {
+                out.push_str(&format!(" = {:?}", v));
+            }
+            out.push('\n');
+            render(out, &n.children, depth + 1);
+        }
+    }
+    let mut out = String::new();
+    render(&mut out, nodes, 0);
+    out
+}
+
+// `flexar test-plugin <name> [--click <id>] [--input <id>=<value>]...`
+// Actions are applied in order, final widget tree is printed.
+// `flexar test-plugin <name> --snapshots [--update]` checks plugin snapshots.
+pub fn run(args: &[String]) -> i32 {
+    let name = match args.first() {
+        Some(n) => n,
+        None => {
+            eprintln!(
+                "usage: flexar test-plugin <name> [--click <id>] [--input <id>=<value>]\n       \
+                 flexar test-plugin <name> --snapshots [--update]"
+            );
+            return 2;
+        }
+    };
+    if let Err(e) = config::Config::check_name(name) {
+        eprintln!("plugin: {}", e);
+        return 2;
+    }
+    let dir = paths::get().plugin(name);
+
+    if args[1..]
+        .iter()
+        .any(|a| a == "--snapshots" || a == "--update")
+    {
+        let update = args[1..].iter().any(|a| a == "--update");
+        let results = snapshot::check(name, &dir, &dir.join("tests"), update);
+        return if snapshot::report(name, &results) {
+            0
+        } else {
+            1
+        };
+    }
+
+    match run_actions(name, &dir, &args[1..]) {
+        Ok(tree) => {
+            print!("{}", tree);
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+fn run_actions(name: &str, dir: &Path, args: &[String]) -> Result<String, String> {
+    let mut h = Harness::load(name, dir)?;
+    h.draw()?;
+    let mut args = args.iter();
+    while let Some(a) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {}", a))?;
+        match a.as_str() {
+            "--click" => h.click(value)?,
+            "--input" => match value.split_once('=') {
+                Some((id, v)) => h.input(id, v)?,
+                None => return Err(format!("expected <id>=<value>, got {}", value)),
+            },
+            _ => return Err(format!("unknown argument {}", a)),
+        }
+        h.draw()?;
+    }
+    eprint!("{}", h.take_output());
+    Ok(render_tree(&h.draw()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn plugin_dir(name: &str, src: &str) -> PathBuf {
+        let dir = paths::test_dir(&format!("harness-{}", name));
+        std::fs::write(dir.join("plugin.lua"), src).unwrap();
+        std::fs::write(dir.join("config.toml"), plugin::test_manifest("")).unwrap();
+        dir
+    }
+
+    const COUNTER: &str = r#"
+        count = 0
+        name = ""
+        function on_load() end
+        function draw(ui)
+            ui:label({ text = "Count: " .. count })
+            ui:button({ id = "inc", text = "+", cb = function() count = count + 1 end })
+            ui:collapsing({ text = "More", cb = function(ui)
+                ui:text_edit({ id = "name", text = name, cb = function(v) name = v end })
+                ui:checkbox({ text = "Flag" })
+                return ui
+            end })
+            ui:show()
+        end
+    "#;
+
+    #[test]
+    fn records_widget_tree() {
+        let mut h = Harness::load("tree", &plugin_dir("tree", COUNTER)).unwrap();
+        let tree = h.draw().unwrap();
+        assert_eq!(
+            render_tree(&tree),
+            "label #label0 \"Count: 0\"\n\
+             button #inc \"+\"\n\
+             collapsing #collapsing2 \"More\"\n\
+             \x20 text_edit #name \"\" = \"\"\n\
+             \x20 checkbox #collapsing2/checkbox1 \"Flag\" = \"false\"\n"
+        );
+    }
+
+    #[test]
+    fn simulates_click_and_input() {
+        let mut h = Harness::load("actions", &plugin_dir("actions", COUNTER)).unwrap();
+        h.draw().unwrap();
+        h.click("inc").unwrap();
+        h.input("name", "Bob").unwrap();
+        let tree = h.draw().unwrap();
+        assert_eq!(tree[0].text, "Count: 1");
+        assert_eq!(tree[2].children[0].value.as_deref(), Some("Bob"));
+        assert!(h.click("name").is_err());
+        assert!(h.click("missing").is_err());
+    }
+
+    #[test]
+    fn reports_draw_errors() {
+        let src = "function on_load() end\nfunction draw(ui) error(\"boom\") end\n";
+        let mut h = Harness::load("error", &plugin_dir("error", src)).unwrap();
+        assert!(h.draw().unwrap_err().contains("boom"));
+    }
+
+    #[test]
+    fn starts_every_run_with_empty_data() {
+        let src = "function on_load()\n\
+            if fs.exists(\"run.txt\") then error(\"data of earlier run\") end\n\
+            fs.write(\"run.txt\", \"x\")\n\
+            end\n";
+        let dir = plugin_dir("data", src);
+        std::fs::write(
+            dir.join("config.toml"),
+            plugin::test_manifest("permissions = [\"fs\"]\n"),
+        )
+        .unwrap();
+
+        let first = Harness::load("data", &dir).unwrap();
+        assert!(first.data_dir.join("run.txt").exists());
+        let second = Harness::load("data", &dir).unwrap();
+        assert_ne!(first.data_dir, second.data_dir);
+
+        let data_dir = first.data_dir.clone();
+        drop(first);
+        assert!(!data_dir.exists());
+    }
+}
@@ -0,0 +1,211 @@
+use std::time::{Duration, Instant};
+
+use toml::value::Table;
+
+use crate::config::Config;
+
+// Edits of the same fields closer than this are merged into one step
+const MERGE_WINDOW: Duration = Duration::from_secs(1);
+
+// Change of some config fields, values are kept before and after it
+pub struct Change {
+    pub fields: Vec<String>,
+    before: Table,
+    after: Table,
+    at: Instant,
+}
+
+// Undo/redo stack over config changes. Every edit goes through edit(),
+// which compares config before and after it.
+#[derive(Default)]
+pub struct History {
+    changes: Vec<Change>,
+    // Number of applied changes
+    pos: usize,
+    // Position where config was saved
+    saved: Option<usize>,
+}
+
+impl History {
+    // Forgets all changes, used when another config is loaded
+    pub fn reset(&mut self) {
+        *self = History::default();
+    }
+
+    // Applies edit to config and records fields it changed
+    pub fn edit<F>(&mut self, conf: &mut Config, edit: F) -> Result<(), String>
+    where
+        F: FnOnce(&mut Config) -> Result<(), String>,
+    {
+        let before = conf.to_table()?;
+        edit(conf)?;
+        self.record(&before, &conf.to_table()?);
+        Ok(())
+    }
+
+    fn record(&mut self, last: &Table, current: &Table) {
+        let mut fields: Vec<String> = current
+            .iter()
+            .filter(|(k, v)| last.get(*k) != Some(v))
+            .map(|(k, _)| k.clone())
+            .collect();
+        fields.extend(last.keys().filter(|k| !current.contains_key(*k)).cloned());
+        if fields.is_empty() {
+            return;
+        }
+        fields.sort();
+
+        let pick = |t: &Table| -> Table {
+            fields
+                .iter()
+                .filter_map(|f| t.get(f).map(|v| (f.clone(), v.clone())))
+                .collect()
+        };
+        let after = pick(current);
+        let now = Instant::now();
+        let merge = self.pos == self.changes.len()
+            && self.saved != Some(self.pos)
+            && self
+                .changes
+                .last()
+                .is_some_and(|c| c.fields == fields && now - c.at < MERGE_WINDOW);
+        if merge {
+            if let Some(c) = self.changes.last_mut() {
+                c.after = after;
+                c.at = now;
+            }
+        } else {
+            self.changes.truncate(self.pos);
+            if self.saved.is_some_and(|s| s > self.pos) {
+                self.saved = None;
+            }
+            self.changes.push(Change {
+                before: pick(last),
+                fields,
+                after,
+                at: now,
+            });
+            self.pos += 1;
+        }
+    }
+
+    // Marks current position as saved
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.pos);
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.pos > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.pos < self.changes.len()
+    }
+
+    // Undoes or redoes changes until `pos` of them are applied
+    pub fn go_to(&mut self, pos: usize, conf: &mut Config) -> Result<(), String> {
+        while self.pos > pos {
+            let c = &self.changes[self.pos - 1];
+            conf.set_fields(&c.fields, &c.before)?;
+            self.pos -= 1;
+        }
+        while self.pos < pos.min(self.changes.len()) {
+            let c = &self.changes[self.pos];
+            conf.set_fields(&c.fields, &c.after)?;
+            self.pos += 1;
+        }
+        Ok(())
+    }
+
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn saved(&self) -> Option<usize> {
+        self.saved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_num(h: &mut History, conf: &mut Config, n: i64) {
+        let mut values = Table::new();
+        values.insert("reg_num".to_owned(), toml::Value::Integer(n));
+        h.edit(conf, |c| c.set_fields(&["reg_num".to_owned()], &values))
+            .unwrap();
+    }
+
+    // Makes next edit a separate step
+    fn age(h: &mut History) {
+        if let Some(c) = h.changes.last_mut() {
+            c.at -= MERGE_WINDOW;
+        }
+    }
+
+    #[test]
+    fn undoes_and_redoes() {
+        let (mut h, mut conf) = (History::default(), Config::default());
+        set_num(&mut h, &mut conf, 20);
+        age(&mut h);
+        h.edit(&mut conf, |c| {
+            c.set_website(Some("vk".to_owned()));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(h.changes().len(), 2);
+        assert_eq!(h.changes()[1].fields, vec!["website"]);
+
+        h.go_to(0, &mut conf).unwrap();
+        assert_eq!((conf.reg_num, conf.website.as_deref()), (10, None));
+        assert!(h.can_redo() && !h.can_undo());
+
+        h.go_to(2, &mut conf).unwrap();
+        assert_eq!((conf.reg_num, conf.website.as_deref()), (20, Some("vk")));
+
+        // new edit after undo drops the redo part
+        h.go_to(1, &mut conf).unwrap();
+        set_num(&mut h, &mut conf, 30);
+        assert_eq!(h.changes().len(), 2);
+        assert!(!h.can_redo());
+    }
+
+    #[test]
+    fn merges_quick_edits_of_same_field() {
+        let (mut h, mut conf) = (History::default(), Config::default());
+        for n in 11..15 {
+            set_num(&mut h, &mut conf, n);
+        }
+        assert_eq!(h.changes().len(), 1);
+        h.go_to(0, &mut conf).unwrap();
+        assert_eq!(conf.reg_num, 10);
+
+        // edits that change nothing aren't recorded
+        set_num(&mut h, &mut conf, 10);
+        assert_eq!(h.pos(), 0);
+    }
+
+    #[test]
+    fn keeps_saved_marker() {
+        let (mut h, mut conf) = (History::default(), Config::default());
+        set_num(&mut h, &mut conf, 20);
+        h.mark_saved();
+        // edit after save isn't merged into the saved step
+        set_num(&mut h, &mut conf, 30);
+        assert_eq!(h.changes().len(), 2);
+        assert_eq!(h.saved(), Some(1));
+
+        h.go_to(1, &mut conf).unwrap();
+        assert_eq!(h.saved(), Some(h.pos()));
+
+        // branching off below the saved step makes it unreachable
+        h.go_to(0, &mut conf).unwrap();
+        set_num(&mut h, &mut conf, 40);
+        assert_eq!(h.saved(), None);
+    }
+}
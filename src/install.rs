@@ -0,0 +1,256 @@
+use std::path::{Path, PathBuf};
+
+use crate::{archive, config, paths, plugin, version::Version};
+
+pub const EXTENSIONS: &[&str] = &["zip"];
+
+// Plugin read from archive and checked, nothing is written yet
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    files: archive::Entries,
+}
+
+// Reads plugin archive. Files may lie at archive root, then plugin is named
+// after the archive, or in a single top directory naming the plugin.
+pub fn inspect(path: &Path) -> Result<Package, String> {
+    let mut files = archive::read(path)?;
+    let name = if files.iter().any(|(p, _)| p == Path::new("config.toml")) {
+        path.file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    } else {
+        let top = match files.first().and_then(|(p, _)| p.components().next()) {
+            Some(c) => PathBuf::from(c.as_os_str()),
+            None => return Err("archive is empty".to_owned()),
+        };
+        if !files.iter().all(|(p, _)| p.starts_with(&top)) {
+            return Err("archive has no config.toml".to_owned());
+        }
+        for (p, _) in files.iter_mut() {
+            *p = p.strip_prefix(&top).unwrap_or(p).to_owned();
+        }
+        top.to_string_lossy().into_owned()
+    };
+    package(name, files)
+}
+
+// Checks plugin files given relative to plugin directory
+pub fn package(name: String, files: archive::Entries) -> Result<Package, String> {
+    config::Config::check_name(&name).map_err(|e| format!("plugin {}", e))?;
+
+    let text = |n: &str| {
+        files
+            .iter()
+            .find(|(p, _)| p == Path::new(n))
+            .ok_or(format!("{}: missing", n))
+            .and_then(|(_, d)| String::from_utf8(d.clone()).map_err(|e| format!("{}: {}", n, e)))
+    };
+    let errors = plugin::validate_sources(text("config.toml"), text("plugin.lua"));
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+    let version = text("config.toml")
+        .ok()
+        .and_then(|m| m.parse::<toml::Value>().ok())
+        .and_then(|m| m.get("version").and_then(|v| v.as_str()).map(str::to_owned))
+        .unwrap_or_default();
+    Ok(Package {
+        name,
+        version,
+        files,
+    })
+}
+
+// Writes package into a hidden directory next to installed plugins
+fn stage(pkg: &Package, dir: &Path) -> Result<(), String> {
+    for (p, data) in &pkg.files {
+        let dest = dir.join(p);
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+        }
+        std::fs::write(&dest, data).map_err(|e| format!("{}: {}", dest.display(), e))?;
+    }
+    Ok(())
+}
+
+// Installs plugin from archive. Installed plugin of the same name is
+// replaced only when updating. Nothing is changed if any step fails.
+pub fn install(path: &Path, update: bool, log: &mut String) -> Result<String, String> {
+    install_package(inspect(path)?, update, log)
+}
+
+pub fn install_package(pkg: Package, update: bool, log: &mut String) -> Result<String, String> {
+    let dest = paths::get().plugin(&pkg.name);
+    let installed = dest
+        .exists()
+        .then(|| plugin::installed_version(&pkg.name).unwrap_or_default());
+    match (&installed, update) {
+        (Some(_), false) => return Err(format!("plugin {} is already installed", pkg.name)),
+        (None, true) => return Err(format!("plugin {} is not installed", pkg.name)),
+        _ => (),
+    }
+
+    let staging = paths::get()
+        .plugins()
+        .join(format!(".{}.install", pkg.name));
+    let _ = std::fs::remove_dir_all(&staging);
+    if let Err(e) = stage(&pkg, &staging) {
+        let _ = std::fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+
+    if let Some(old_version) = installed {
+        let old = paths::get().plugins().join(format!(".{}.old", pkg.name));
+        if let Err(e) = replace_dir(&staging, &dest, &old) {
+            let _ = std::fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+        let newer = match (Version::parse(&pkg.version), Version::parse(&old_version)) {
+            (Ok(new), Ok(old)) => new > old,
+            _ => true,
+        };
+        let verb = if newer { "updated" } else { "replaced" };
+        log.push_str(&format!(
+            "Plugin {} was {} from {} to {}.\n",
+            pkg.name, verb, old_version, pkg.version
+        ));
+    } else {
+        if let Err(e) = std::fs::rename(&staging, &dest) {
+            let _ = std::fs::remove_dir_all(&staging);
+            return Err(format!("{}: {}", dest.display(), e));
+        }
+        log.push_str(&format!(
+            "Plugin {} {} was installed.\n",
+            pkg.name, pkg.version
+        ));
+    }
+    Ok(pkg.name)
+}
+
+// Puts staged directory in place of dest. Dest is moved aside to old first
+// and moved back if the staged one can't take its place.
+fn replace_dir(staging: &Path, dest: &Path, old: &Path) -> Result<(), String> {
+    let _ = std::fs::remove_dir_all(old);
+    std::fs::rename(dest, old).map_err(|e| format!("{}: {}", dest.display(), e))?;
+    if let Err(e) = std::fs::rename(staging, dest) {
+        let _ = std::fs::rename(old, dest);
+        return Err(format!("{}: {}", dest.display(), e));
+    }
+    let _ = std::fs::remove_dir_all(old);
+    Ok(())
+}
+
+// Removes plugin files, data written by plugin is kept
+pub fn uninstall(name: &str) -> Result<(), String> {
+    config::Config::check_name(name)?;
+    let dir = paths::get().plugin(name);
+    if !dir.join("config.toml").is_file() {
+        return Err(format!("plugin {} is not installed", name));
+    }
+    std::fs::remove_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn manifest(version: &str) -> String {
+        plugin::test_manifest(&format!("version = \"{}\"\n", version))
+    }
+
+    const SCRIPT: &str = "function on_load() end\nfunction draw(ui) end\n";
+
+    // Writes zip with raw entry names, so unsafe ones can be made too
+    fn zip(name: &str, entries: &[(&str, &str)]) -> PathBuf {
+        let dir = paths::get().root().join("zips");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.zip", name));
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        for (n, data) in entries {
+            zip.start_file(*n, zip::write::FileOptions::default())
+                .unwrap();
+            zip.write_all(data.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+        path
+    }
+
+    fn plugin_zip(name: &str, version: &str) -> PathBuf {
+        zip(
+            name,
+            &[("config.toml", &manifest(version)), ("plugin.lua", SCRIPT)],
+        )
+    }
+
+    #[test]
+    fn refuses_entries_outside_root() {
+        let m = manifest("1.0.0");
+        for (name, bad) in [("dotdot", "../evil.lua"), ("absolute", "/tmp/evil.lua")] {
+            let path = zip(
+                name,
+                &[("config.toml", &m), ("plugin.lua", SCRIPT), (bad, "x")],
+            );
+            let err = install(&path, false, &mut String::new()).unwrap_err();
+            assert!(err.contains("unsafe path"), "{}", err);
+            assert!(!paths::get().plugin(name).exists());
+        }
+    }
+
+    #[test]
+    fn installs_updates_and_uninstalls() {
+        let mut log = String::new();
+        let v1 = plugin_zip("cycle", "1.0.0");
+        assert_eq!(install(&v1, false, &mut log).unwrap(), "cycle");
+        assert!(install(&v1, false, &mut log)
+            .unwrap_err()
+            .contains("already installed"));
+
+        let dir = paths::get().root().join("v2");
+        std::fs::create_dir_all(&dir).unwrap();
+        let v2 = dir.join("cycle.zip");
+        std::fs::rename(plugin_zip("cycle-v2", "1.1.0"), &v2).unwrap();
+        install(&v2, true, &mut log).unwrap();
+        assert!(log.contains("updated from 1.0.0 to 1.1.0"), "{}", log);
+        assert_eq!(plugin::installed_version("cycle").as_deref(), Some("1.1.0"));
+        assert!(!paths::get().plugins().join(".cycle.old").exists());
+        assert!(!paths::get().plugins().join(".cycle.install").exists());
+
+        let data = paths::get().plugin_data("cycle");
+        std::fs::create_dir_all(&data).unwrap();
+        uninstall("cycle").unwrap();
+        assert!(!paths::get().plugin("cycle").exists());
+        assert!(data.exists());
+        assert!(uninstall("cycle").unwrap_err().contains("not installed"));
+    }
+
+    #[test]
+    fn restores_old_version_when_replace_fails() {
+        let root = paths::get().root().join("replace");
+        let (dest, old) = (root.join("plugin"), root.join(".plugin.old"));
+        std::fs::create_dir_all(&dest).unwrap();
+        std::fs::write(dest.join("plugin.lua"), "old").unwrap();
+
+        // staged directory is missing, so it can't be moved into place
+        assert!(replace_dir(&root.join(".plugin.install"), &dest, &old).is_err());
+        assert_eq!(
+            std::fs::read_to_string(dest.join("plugin.lua")).unwrap(),
+            "old"
+        );
+        assert!(!old.exists());
+    }
+
+    #[test]
+    fn refuses_invalid_plugin() {
+        let path = zip(
+            "invalid",
+            &[
+                ("config.toml", &manifest("1.0.0")),
+                ("plugin.lua", "function ("),
+            ],
+        );
+        assert!(install(&path, false, &mut String::new()).is_err());
+        assert!(!paths::get().plugin("invalid").exists());
+    }
+}
@@ -0,0 +1,281 @@
+use std::{
+    io::Write,
+    path::{Component, Path},
+};
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{archive, paths, unpack};
+
+// Manifest may list SHA-256 of plugin files in `files` table. Signature is
+// ed25519 over the manifest bytes, hex encoded next to it.
+pub const MANIFEST: &str = "config.toml";
+pub const SIGNATURE: &str = "config.toml.sig";
+const KEY_EXT: &str = "pub";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Status {
+    // Signed with the named trusted key, files match the manifest
+    Verified(String),
+    Unsigned,
+    Tampered(String),
+}
+
+impl Status {
+    pub fn label(&self) -> String {
+        match self {
+            Status::Verified(key) => format!("verified ({})", key),
+            Status::Unsigned => "unsigned".to_owned(),
+            Status::Tampered(reason) => format!("tampered: {}", reason),
+        }
+    }
+}
+
+// Whether plugins without a valid signature may run. Tampered ones never do.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Policy {
+    #[default]
+    Allow,
+    Refuse,
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex<const N: usize>(s: &str) -> Option<[u8; N]> {
+    let s = s.trim();
+    if s.len() != N * 2 || !s.is_ascii() {
+        return None;
+    }
+    let mut out = [0; N];
+    for (i, b) in out.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(out)
+}
+
+// Reads trusted keys, named after their files
+pub fn trusted_keys(log: &mut String) -> Vec<(String, VerifyingKey)> {
+    let dir = paths::get().trusted_keys();
+    let rd = match std::fs::read_dir(&dir) {
+        Ok(rd) => rd,
+        Err(_) => return Vec::new(),
+    };
+    let mut keys: Vec<(String, VerifyingKey)> = rd
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e == KEY_EXT))
+        .filter_map(|p| {
+            let name = p.file_stem()?.to_string_lossy().into_owned();
+            let key = std::fs::read_to_string(&p)
+                .ok()
+                .and_then(|s| from_hex::<32>(&s))
+                .and_then(|b| VerifyingKey::from_bytes(&b).ok());
+            if key.is_none() {
+                log.push_str(&format!("{}: invalid public key\n", p.display()));
+            }
+            Some((name, key?))
+        })
+        .collect();
+    keys.sort_by(|a, b| a.0.cmp(&b.0));
+    keys
+}
+
+// Checks plugin files against its manifest and the manifest signature
+pub fn check(dir: &Path, keys: &[(String, VerifyingKey)]) -> Status {
+    let manifest = match std::fs::read(dir.join(MANIFEST)) {
+        Ok(data) => data,
+        Err(_) => return Status::Unsigned,
+    };
+    let files = toml::from_slice::<toml::Value>(&manifest)
+        .ok()
+        .and_then(|v| v.get("files").and_then(|f| f.as_table()).cloned());
+
+    if let Some(files) = &files {
+        for (rel, sum) in files {
+            if !Path::new(rel)
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)))
+            {
+                return Status::Tampered(format!("invalid path {}", rel));
+            }
+            match std::fs::read(dir.join(rel)) {
+                Err(_) => return Status::Tampered(format!("{} is missing", rel)),
+                Ok(data) => {
+                    let sum = sum.as_str().unwrap_or_default();
+                    if !unpack::checksum(&data).eq_ignore_ascii_case(sum) {
+                        return Status::Tampered(format!("{} was changed", rel));
+                    }
+                }
+            }
+        }
+        // unlisted scripts could still be loaded with require
+        if let Ok(all) = archive::collect(dir, Path::new("")) {
+            for (p, _) in all {
+                let name = archive::entry_name(&p);
+                if p.extension().is_some_and(|e| e == "lua") && !files.contains_key(&name) {
+                    return Status::Tampered(format!("{} is not listed", name));
+                }
+            }
+        }
+    }
+
+    let sig = match std::fs::read_to_string(dir.join(SIGNATURE)) {
+        Ok(s) => s,
+        Err(_) => return Status::Unsigned,
+    };
+    if files.is_none() {
+        return Status::Tampered("signed manifest lists no files".to_owned());
+    }
+    let sig = match from_hex::<64>(&sig) {
+        Some(b) => Signature::from_bytes(&b),
+        None => return Status::Tampered("signature is malformed".to_owned()),
+    };
+    match keys.iter().find(|(_, k)| k.verify(&manifest, &sig).is_ok()) {
+        Some((name, _)) => Status::Verified(name.clone()),
+        None => Status::Tampered("signature doesn't match any trusted key".to_owned()),
+    }
+}
+
+// Writes new key pair, secret to path and public key next to it as *.pub
+pub fn generate_key(path: &Path) -> Result<(), String> {
+    let mut secret = [0; 32];
+    getrandom::getrandom(&mut secret).map_err(|e| e.to_string())?;
+    let key = SigningKey::from_bytes(&secret);
+    if path.exists() {
+        return Err(format!("{} already exists", path.display()));
+    }
+    write_secret(path, &to_hex(&secret)).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let public = path.with_extension(KEY_EXT);
+    std::fs::write(&public, to_hex(key.verifying_key().as_bytes()))
+        .map_err(|e| format!("{}: {}", public.display(), e))
+}
+
+// Secret key is readable by its owner only
+fn write_secret(path: &Path, data: &str) -> std::io::Result<()> {
+    let mut opts = std::fs::OpenOptions::new();
+    opts.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut opts, 0o600);
+    opts.open(path)?.write_all(data.as_bytes())
+}
+
+// Lists checksums of all plugin files in its manifest and signs it
+pub fn sign(dir: &Path, key_path: &Path) -> Result<(), String> {
+    let secret =
+        std::fs::read_to_string(key_path).map_err(|e| format!("{}: {}", key_path.display(), e))?;
+    let key = from_hex::<32>(&secret)
+        .map(|b| SigningKey::from_bytes(&b))
+        .ok_or(format!("{}: invalid secret key", key_path.display()))?;
+
+    let path = dir.join(MANIFEST);
+    let text = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut manifest: toml::value::Table =
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut files = toml::value::Table::new();
+    for (p, data) in archive::collect(dir, Path::new(""))? {
+        let name = archive::entry_name(&p);
+        if name != MANIFEST && name != SIGNATURE {
+            files.insert(name, toml::Value::String(unpack::checksum(&data)));
+        }
+    }
+    manifest.insert("files".to_owned(), toml::Value::Table(files));
+    let data = toml::to_string(&toml::Value::Table(manifest)).map_err(|e| e.to_string())?;
+    std::fs::write(&path, &data).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let sig = key.sign(data.as_bytes());
+    let sig_path = dir.join(SIGNATURE);
+    std::fs::write(&sig_path, to_hex(&sig.to_bytes()))
+        .map_err(|e| format!("{}: {}", sig_path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::{self, Plugin};
+
+    // Plugin directory with a key pair to sign it
+    fn plugin(name: &str) -> (std::path::PathBuf, std::path::PathBuf) {
+        let root = paths::get().root().join("integrity").join(name);
+        let _ = std::fs::remove_dir_all(&root);
+        let dir = root.join("plugin");
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(
+            dir.join(MANIFEST),
+            plugin::test_manifest("version = \"1.0.0\"\n"),
+        )
+        .unwrap();
+        std::fs::write(dir.join("plugin.lua"), "function draw(ui) end\n").unwrap();
+        std::fs::write(dir.join("lib/util.lua"), "return {}\n").unwrap();
+        let key = root.join("signer");
+        generate_key(&key).unwrap();
+        (dir, key)
+    }
+
+    fn keys(key: &Path) -> Vec<(String, VerifyingKey)> {
+        let public = std::fs::read_to_string(key.with_extension(KEY_EXT)).unwrap();
+        let public = VerifyingKey::from_bytes(&from_hex::<32>(&public).unwrap()).unwrap();
+        vec![("signer".to_owned(), public)]
+    }
+
+    #[test]
+    fn verifies_signed_plugin() {
+        let (dir, key) = plugin("signed");
+        assert_eq!(check(&dir, &keys(&key)), Status::Unsigned);
+        sign(&dir, &key).unwrap();
+        assert_eq!(
+            check(&dir, &keys(&key)),
+            Status::Verified("signer".to_owned())
+        );
+        assert!(matches!(check(&dir, &[]), Status::Tampered(_)));
+    }
+
+    #[test]
+    fn detects_changed_and_unlisted_files() {
+        let (dir, key) = plugin("tampered");
+        sign(&dir, &key).unwrap();
+        std::fs::write(dir.join("lib/util.lua"), "return nil\n").unwrap();
+        assert_eq!(
+            check(&dir, &keys(&key)),
+            Status::Tampered("lib/util.lua was changed".to_owned())
+        );
+
+        sign(&dir, &key).unwrap();
+        std::fs::write(dir.join("extra.lua"), "os = nil\n").unwrap();
+        assert_eq!(
+            check(&dir, &keys(&key)),
+            Status::Tampered("extra.lua is not listed".to_owned())
+        );
+    }
+
+    #[test]
+    fn missing_signature_is_unsigned() {
+        let (dir, key) = plugin("unsigned");
+        sign(&dir, &key).unwrap();
+        std::fs::remove_file(dir.join(SIGNATURE)).unwrap();
+        assert_eq!(check(&dir, &keys(&key)), Status::Unsigned);
+    }
+
+    #[test]
+    fn refuse_policy_blocks_unsigned() {
+        let (dir, _) = plugin("policy");
+        let data = dir.with_file_name("data");
+        let mut plugin = Plugin::new();
+        plugin.set_policy(Policy::Refuse);
+        let mut log = String::new();
+        assert!(!plugin.load_dir("policy".to_owned(), dir, data, None, &mut log));
+        assert!(log.contains("unsigned plugins are not allowed"), "{}", log);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn secret_key_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let (_, key) = plugin("mode");
+        let mode = std::fs::metadata(&key).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(generate_key(&key).unwrap_err().contains("already exists"));
+    }
+}
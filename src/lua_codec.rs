@@ -0,0 +1,356 @@
+use mlua::{Lua, MultiValue, Table, ToLua, Value};
+
+// Limits for encoding lua tables. Shared subtables are encoded once per
+// reference, so total count is limited as well as nesting.
+const MAX_DEPTH: usize = 128;
+const MAX_VALUES: usize = 1_000_000;
+
+// State of walk over value being encoded
+#[derive(Default)]
+struct Walk<'lua> {
+    // Tables from the top value down to the current one
+    path: Vec<Table<'lua>>,
+    values: usize,
+}
+
+impl<'lua> Walk<'lua> {
+    fn visit(&mut self) -> Result<(), String> {
+        self.values += 1;
+        if self.values > MAX_VALUES {
+            return Err(format!("more than {} values", MAX_VALUES));
+        }
+        Ok(())
+    }
+
+    fn enter(&mut self, t: &Table<'lua>) -> Result<(), String> {
+        if self.path.contains(t) {
+            return Err("table contains itself".to_owned());
+        }
+        if self.path.len() == MAX_DEPTH {
+            return Err(format!("tables are nested deeper than {}", MAX_DEPTH));
+        }
+        self.path.push(t.clone());
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.path.pop();
+    }
+}
+
+// Lua table is encoded as array if its keys are exactly 1..n
+fn is_array(table: &Table) -> mlua::Result<bool> {
+    let len = table.raw_len() as usize;
+    let mut count = 0;
+    for pair in table.clone().pairs::<Value, Value>() {
+        pair?;
+        count += 1;
+    }
+    Ok(len > 0 && count == len)
+}
+
+fn type_err(what: &str, v: &Value) -> String {
+    format!("can't encode {} value to {}", v.type_name(), what)
+}
+
+fn lua_to_toml<'lua>(value: &Value<'lua>, walk: &mut Walk<'lua>) -> Result<toml::Value, String> {
+    walk.visit()?;
+    Ok(match value {
+        Value::Boolean(b) => toml::Value::Boolean(*b),
+        Value::Integer(i) => toml::Value::Integer(*i),
+        Value::Number(n) => toml::Value::Float(*n),
+        Value::String(s) => toml::Value::String(s.to_str().map_err(|e| e.to_string())?.to_owned()),
+        Value::Table(t) => {
+            walk.enter(t)?;
+            let v = if is_array(t).map_err(|e| e.to_string())? {
+                let mut arr = Vec::new();
+                for v in t.clone().sequence_values::<Value>() {
+                    arr.push(lua_to_toml(&v.map_err(|e| e.to_string())?, walk)?);
+                }
+                toml::Value::Array(arr)
+            } else {
+                let mut map = toml::value::Table::new();
+                for pair in t.clone().pairs::<Value, Value>() {
+                    let (k, v) = pair.map_err(|e| e.to_string())?;
+                    map.insert(table_key(&k)?, lua_to_toml(&v, walk)?);
+                }
+                toml::Value::Table(map)
+            };
+            walk.leave();
+            v
+        }
+        v => return Err(type_err("toml", v)),
+    })
+}
+
+pub fn toml_to_lua<'lua>(lua: &'lua Lua, value: &toml::Value) -> mlua::Result<Value<'lua>> {
+    match value {
+        toml::Value::String(s) => s.as_str().to_lua(lua),
+        toml::Value::Integer(i) => i.to_lua(lua),
+        toml::Value::Float(f) => f.to_lua(lua),
+        toml::Value::Boolean(b) => b.to_lua(lua),
+        toml::Value::Datetime(d) => d.to_string().to_lua(lua),
+        toml::Value::Array(arr) => {
+            let t = lua.create_table()?;
+            for (i, v) in arr.iter().enumerate() {
+                t.raw_set(i + 1, toml_to_lua(lua, v)?)?;
+            }
+            Ok(Value::Table(t))
+        }
+        toml::Value::Table(map) => {
+            let t = lua.create_table()?;
+            for (k, v) in map {
+                t.raw_set(k.as_str(), toml_to_lua(lua, v)?)?;
+            }
+            Ok(Value::Table(t))
+        }
+    }
+}
+
+fn lua_to_json<'lua>(
+    value: &Value<'lua>,
+    walk: &mut Walk<'lua>,
+) -> Result<serde_json::Value, String> {
+    walk.visit()?;
+    Ok(match value {
+        Value::Nil => serde_json::Value::Null,
+        Value::Boolean(b) => serde_json::Value::Bool(*b),
+        Value::Integer(i) => serde_json::Value::from(*i),
+        Value::Number(n) => match serde_json::Number::from_f64(*n) {
+            Some(n) => serde_json::Value::Number(n),
+            None => return Err(format!("can't encode {} to json", n)),
+        },
+        Value::String(s) => {
+            serde_json::Value::String(s.to_str().map_err(|e| e.to_string())?.to_owned())
+        }
+        Value::Table(t) => {
+            walk.enter(t)?;
+            let v = if is_array(t).map_err(|e| e.to_string())? {
+                let mut arr = Vec::new();
+                for v in t.clone().sequence_values::<Value>() {
+                    arr.push(lua_to_json(&v.map_err(|e| e.to_string())?, walk)?);
+                }
+                serde_json::Value::Array(arr)
+            } else {
+                let mut map = serde_json::Map::new();
+                for pair in t.clone().pairs::<Value, Value>() {
+                    let (k, v) = pair.map_err(|e| e.to_string())?;
+                    map.insert(table_key(&k)?, lua_to_json(&v, walk)?);
+                }
+                serde_json::Value::Object(map)
+            };
+            walk.leave();
+            v
+        }
+        v => return Err(type_err("json", v)),
+    })
+}
+
+// JSON null is decoded as nil
+fn json_to_lua<'lua>(lua: &'lua Lua, value: &serde_json::Value) -> mlua::Result<Value<'lua>> {
+    match value {
+        serde_json::Value::Null => Ok(Value::Nil),
+        serde_json::Value::Bool(b) => b.to_lua(lua),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => i.to_lua(lua),
+            None => n.as_f64().unwrap_or(f64::NAN).to_lua(lua),
+        },
+        serde_json::Value::String(s) => s.as_str().to_lua(lua),
+        serde_json::Value::Array(arr) => {
+            let t = lua.create_table()?;
+            for (i, v) in arr.iter().enumerate() {
+                t.raw_set(i + 1, json_to_lua(lua, v)?)?;
+            }
+            Ok(Value::Table(t))
+        }
+        serde_json::Value::Object(map) => {
+            let t = lua.create_table()?;
+            for (k, v) in map {
+                t.raw_set(k.as_str(), json_to_lua(lua, v)?)?;
+            }
+            Ok(Value::Table(t))
+        }
+    }
+}
+
+fn table_key(key: &Value) -> Result<String, String> {
+    match key {
+        Value::String(s) => Ok(s.to_str().map_err(|e| e.to_string())?.to_owned()),
+        Value::Integer(i) => Ok(i.to_string()),
+        k => Err(format!("can't use {} as a key", k.type_name())),
+    }
+}
+
+// Parse failure returned to lua as `nil, message, line, column`
+fn parse_error<'lua>(
+    lua: &'lua Lua,
+    msg: String,
+    line_col: Option<(usize, usize)>,
+) -> mlua::Result<MultiValue<'lua>> {
+    let mut values = vec![Value::Nil, msg.to_lua(lua)?];
+    if let Some((line, col)) = line_col {
+        values.push(line.to_lua(lua)?);
+        values.push(col.to_lua(lua)?);
+    }
+    Ok(MultiValue::from_vec(values))
+}
+
+// Creates `toml` table for lua
+pub fn create_toml_api(lua: &Lua) -> mlua::Result<Table<'_>> {
+    let api = lua.create_table()?;
+
+    api.set(
+        "decode",
+        lua.create_function(|lua, src: String| match src.parse::<toml::Value>() {
+            Ok(v) => Ok(MultiValue::from_vec(vec![toml_to_lua(lua, &v)?])),
+            Err(e) => {
+                // toml reports zero-based positions
+                let line_col = e.line_col().map(|(l, c)| (l + 1, c + 1));
+                parse_error(lua, format!("toml.decode: {}", e), line_col)
+            }
+        })?,
+    )?;
+
+    api.set(
+        "encode",
+        lua.create_function(|_, value: Value| {
+            let err = |e: String| mlua::Error::RuntimeError(format!("toml.encode: {}", e));
+            match lua_to_toml(&value, &mut Walk::default()).map_err(err)? {
+                v @ toml::Value::Table(_) => toml::to_string(&v).map_err(|e| err(e.to_string())),
+                _ => Err(err("top-level value must be a table".to_owned())),
+            }
+        })?,
+    )?;
+
+    Ok(api)
+}
+
+// Creates `json` table for lua
+pub fn create_json_api(lua: &Lua) -> mlua::Result<Table<'_>> {
+    let api = lua.create_table()?;
+
+    api.set(
+        "decode",
+        lua.create_function(|lua, src: String| {
+            match serde_json::from_str::<serde_json::Value>(&src) {
+                Ok(v) => Ok(MultiValue::from_vec(vec![json_to_lua(lua, &v)?])),
+                Err(e) => parse_error(
+                    lua,
+                    format!("json.decode: {}", e),
+                    Some((e.line(), e.column())),
+                ),
+            }
+        })?,
+    )?;
+
+    api.set(
+        "encode",
+        lua.create_function(|_, (value, pretty): (Value, Option<bool>)| {
+            let err = |e: String| mlua::Error::RuntimeError(format!("json.encode: {}", e));
+            let v = lua_to_json(&value, &mut Walk::default()).map_err(err)?;
+            let res = if pretty.unwrap_or(false) {
+                serde_json::to_string_pretty(&v)
+            } else {
+                serde_json::to_string(&v)
+            };
+            res.map_err(|e| err(e.to_string()))
+        })?,
+    )?;
+
+    Ok(api)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        lua.globals()
+            .set("toml", create_toml_api(&lua).unwrap())
+            .unwrap();
+        lua.globals()
+            .set("json", create_json_api(&lua).unwrap())
+            .unwrap();
+        lua
+    }
+
+    #[test]
+    fn round_trips() {
+        let lua = lua();
+        let ok: bool = lua
+            .load(
+                r#"
+                local t = { name = "x", n = 3, f = 1.5, on = true, list = { 1, 2, 3 },
+                            sub = { a = "b" } }
+                local function same(a, b)
+                    return a.name == b.name and a.n == b.n and a.f == b.f and a.on == b.on
+                        and #a.list == #b.list and a.list[3] == b.list[3] and a.sub.a == b.sub.a
+                end
+                return same(t, toml.decode(toml.encode(t))) and same(t, json.decode(json.encode(t)))
+                    and same(t, json.decode(json.encode(t, true)))
+                "#,
+            )
+            .eval()
+            .unwrap();
+        assert!(ok);
+        let null: bool = lua
+            .load("return json.decode('[null]')[1] == nil")
+            .eval()
+            .unwrap();
+        assert!(null);
+    }
+
+    #[test]
+    fn rejects_cycles() {
+        let lua = lua();
+        for api in ["toml", "json"] {
+            let err = lua
+                .load(&format!(
+                    "local t = {{}}; t[1] = t; t[2] = t; return {}.encode({{ t = t }})",
+                    api
+                ))
+                .eval::<String>()
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains("contains itself"), "{}", err);
+        }
+        // a table reached twice without a cycle is fine
+        let s: String = lua
+            .load("local s = { 1 }; return json.encode({ s, s })")
+            .eval()
+            .unwrap();
+        assert_eq!(s, "[[1],[1]]");
+    }
+
+    #[test]
+    fn limits_shared_tables() {
+        let lua = lua();
+        let err = lua
+            .load("local t = { 1 }; for i = 1, 40 do t = { t, t } end; return json.encode(t)")
+            .eval::<String>()
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("more than"), "{}", err);
+    }
+
+    #[test]
+    fn reports_decode_position() {
+        let lua = lua();
+        let (v, msg, line, col): (Value, String, usize, usize) = lua
+            .load("return toml.decode('a = 1\\nb = ')")
+            .eval()
+            .unwrap();
+        assert!(matches!(v, Value::Nil));
+        assert!(msg.starts_with("toml.decode"));
+        assert_eq!((line, col), (2, 5));
+
+        let (v, msg, line, col): (Value, String, usize, usize) = lua
+            .load("return json.decode('{\\n  \"a\": }')")
+            .eval()
+            .unwrap();
+        assert!(matches!(v, Value::Nil));
+        assert!(msg.starts_with("json.decode"));
+        assert_eq!((line, col), (2, 8));
+    }
+}
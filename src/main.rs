@@ -2,10 +2,41 @@ use std::{cell::RefCell, rc::Rc};
 
 use eframe::{egui, epi};
 
+mod archive;
+mod backup;
+mod cli;
 mod config;
 mod dialogs;
+mod file_browser;
+mod form;
+mod harness;
+mod history;
+mod install;
+mod integrity;
+mod lua_codec;
+mod paths;
+mod permissions;
 mod plugin;
+mod plugin_fs;
+mod plugin_settings;
 mod plugin_ui;
+mod profile;
+mod repo;
+mod schema;
+mod session;
+mod settings;
+mod snapshot;
+mod unpack;
+mod version;
+
+// Action postponed until unsaved changes are saved or discarded
+enum Pending {
+    Exit,
+    Open,
+    Import,
+    SwitchPlugin(String),
+    SwitchProfile(String),
+}
 
 struct FlexApp {
     about_w: bool,
@@ -15,18 +46,380 @@ struct FlexApp {
     log: String,
     lua_plugin: plugin::Plugin,
     lua_log: Rc<RefCell<String>>,
+    pending: Option<Pending>,
+    // Save dialog was opened to resolve pending action
+    awaiting_save: bool,
+    title: String,
+    session: session::Session,
+    history: history::History,
+    history_w: bool,
+    profile_dialog: dialogs::ProfileDialog,
+    unpack_dialog: dialogs::UnpackDialog,
+    install_dialog: dialogs::InstallDialog,
+    plugin_browser: dialogs::PluginBrowser,
+    plugin_doc: dialogs::PluginDoc,
+    settings: settings::Settings,
+    // Installed plugins with their manifests, reread when plugins may have changed
+    plugins: Vec<(String, plugin::Info)>,
+    // Shape of Config for the generated editor
+    schema: schema::Shape,
 }
 
 impl Default for FlexApp {
     fn default() -> Self {
+        let mut config_file = config::Config::default();
+        config_file.mark_clean();
         Self {
             about_w: false,
             conf_dialog: dialogs::ConfigDialog::default(),
-            config_file: config::Config::default(),
+            config_file,
             reg_count: 0,
             log: String::from("Welcome to the FlexAR!\n"),
             lua_plugin: plugin::Plugin::new(),
             lua_log: Rc::new(RefCell::new(String::new())),
+            pending: None,
+            awaiting_save: false,
+            title: String::new(),
+            session: session::Session::default(),
+            history: history::History::default(),
+            history_w: false,
+            profile_dialog: dialogs::ProfileDialog::default(),
+            unpack_dialog: dialogs::UnpackDialog::default(),
+            install_dialog: dialogs::InstallDialog::default(),
+            plugin_browser: dialogs::PluginBrowser::default(),
+            plugin_doc: dialogs::PluginDoc::default(),
+            settings: settings::Settings::default(),
+            plugins: Vec::new(),
+            schema: schema::trace::<config::Config>(),
+        }
+    }
+}
+
+impl FlexApp {
+    // Runs action, asking first if config has unsaved changes
+    fn request(&mut self, action: Pending, frame: &epi::Frame) {
+        if self.config_file.is_dirty() {
+            self.pending = Some(action);
+        } else {
+            self.run(action, frame);
+        }
+    }
+
+    fn run(&mut self, action: Pending, frame: &epi::Frame) {
+        match action {
+            Pending::Exit => {
+                self.lua_plugin.shutdown(&mut self.log);
+                frame.quit();
+            }
+            Pending::Open => self.conf_dialog.open_dialog(&mut self.log),
+            Pending::Import => self.conf_dialog.import_dialog(),
+            Pending::SwitchPlugin(name) => {
+                self.lua_plugin.load(
+                    Some(name.clone()),
+                    self.config_file.plugin_settings.get(&name),
+                    &mut self.log,
+                    self.lua_log.clone(),
+                );
+                self.edit_config(|c| {
+                    c.set_website(Some(name));
+                    Ok(())
+                });
+            }
+            Pending::SwitchProfile(name) => self.switch_profile(name, frame),
+        }
+    }
+
+    fn refresh_plugins(&mut self) {
+        self.plugins = plugin::list_with_info(&mut self.log);
+    }
+
+    // Brings current plugin in line with installed files
+    fn plugin_changed(&mut self, change: dialogs::PluginChange, frame: &epi::Frame) {
+        self.refresh_plugins();
+        match change {
+            // updated plugin is restarted with new files
+            dialogs::PluginChange::Installed(name)
+                if self.config_file.website.as_ref() == Some(&name) =>
+            {
+                self.run(Pending::SwitchPlugin(name), frame)
+            }
+            dialogs::PluginChange::Uninstalled(name)
+                if self.config_file.website.as_ref() == Some(&name) =>
+            {
+                self.lua_plugin
+                    .load(None, None, &mut self.log, self.lua_log.clone());
+                self.edit_config(|c| {
+                    c.set_website(None);
+                    Ok(())
+                });
+            }
+            _ => (),
+        }
+    }
+
+    // Loads config of profile with its plugin, settings and layout
+    fn switch_profile(&mut self, name: String, frame: &epi::Frame) {
+        let p = match profile::Profile::load(&name) {
+            Ok(p) => p,
+            Err(e) => {
+                self.log.push_str(&format!("{}\n", e));
+                return;
+            }
+        };
+        let c = self.conf_dialog.open_profile(
+            &p,
+            &mut self.lua_plugin,
+            &mut self.log,
+            self.lua_log.clone(),
+        );
+        match c {
+            Some(c) => self.set_config(c),
+            None => {
+                self.log
+                    .push_str(&format!("Profile {} was not applied.\n", name));
+                return;
+            }
+        }
+        let installed = self.lua_plugin.version();
+        if p.plugin.as_deref() == Some(self.lua_plugin.name())
+            && !p.plugin_version.is_empty()
+            && installed != p.plugin_version
+        {
+            self.log.push_str(&format!(
+                "Profile {} was saved with {} {}, installed version is {}.\n",
+                name,
+                self.lua_plugin.name(),
+                p.plugin_version,
+                installed
+            ));
+        }
+        self.session.set_layout(&p.layout);
+        if let Some([w, h]) = p.layout.window_size {
+            frame.set_window_size(egui::vec2(w, h));
+        }
+        self.log
+            .push_str(&format!("Profile {} was loaded.\n", name));
+        self.session.profile = Some(name);
+    }
+
+    // Saves current config and keeps it as profile with plugin and layout
+    fn save_profile(&mut self, name: &str, window_size: egui::Vec2) {
+        if self.conf_dialog.name().is_empty() {
+            self.log
+                .push_str("Config must be saved before it is used in a profile.\n");
+            return;
+        }
+        if self.config_file.is_dirty()
+            && !self.conf_dialog.save_current(
+                &mut self.config_file,
+                &self.lua_plugin,
+                &mut self.log,
+            )
+        {
+            return;
+        }
+        let plugin = self.config_file.website.clone();
+        let loaded = plugin.as_deref() == Some(self.lua_plugin.name());
+        let p = profile::Profile {
+            config: self.conf_dialog.name().to_owned(),
+            plugin_version: if loaded {
+                self.lua_plugin.version().to_owned()
+            } else {
+                String::new()
+            },
+            settings: if loaded {
+                self.lua_plugin.settings()
+            } else {
+                plugin
+                    .as_ref()
+                    .and_then(|n| self.config_file.plugin_settings.get(n))
+                    .cloned()
+                    .unwrap_or_default()
+            },
+            plugin,
+            layout: self.session.layout([window_size.x, window_size.y]),
+        };
+        match p.save(name) {
+            Ok(_) => {
+                self.log.push_str(&format!("Profile {} was saved.\n", name));
+                self.session.profile = Some(name.to_owned());
+            }
+            Err(e) => self.log.push_str(&format!("{}: {}\n", name, e)),
+        }
+        self.profile_dialog.refresh(&mut self.log);
+    }
+
+    fn resolve_pending(&mut self, ctx: &egui::CtxRef, frame: &epi::Frame) {
+        if self.pending.is_none() {
+            return;
+        }
+        if self.awaiting_save {
+            if !self.config_file.is_dirty() {
+                self.awaiting_save = false;
+                if let Some(action) = self.pending.take() {
+                    self.run(action, frame);
+                }
+            } else if !self.conf_dialog.is_saving() {
+                // save dialog was closed without saving
+                self.awaiting_save = false;
+                self.pending = None;
+            }
+            return;
+        }
+        match dialogs::unsaved(ctx, self.conf_dialog.name()) {
+            Some(dialogs::Unsaved::Save) if self.conf_dialog.name().is_empty() => {
+                self.conf_dialog.save_dialog(&mut self.log);
+                self.awaiting_save = true;
+            }
+            Some(dialogs::Unsaved::Save) => {
+                if self.conf_dialog.save_current(
+                    &mut self.config_file,
+                    &self.lua_plugin,
+                    &mut self.log,
+                ) {
+                    if let Some(action) = self.pending.take() {
+                        self.run(action, frame);
+                    }
+                } else {
+                    self.pending = None;
+                }
+            }
+            Some(dialogs::Unsaved::Discard) => {
+                if let Some(action) = self.pending.take() {
+                    // plugin switch keeps the config, so changes are dropped first
+                    if let Pending::SwitchPlugin(_) = action {
+                        self.revert_config();
+                    }
+                    self.run(action, frame);
+                }
+            }
+            Some(dialogs::Unsaved::Cancel) => self.pending = None,
+            None => (),
+        }
+    }
+
+    fn restore_session(&mut self, storage: Option<&dyn epi::Storage>, frame: &epi::Frame) {
+        self.session = session::load(storage);
+        if let Some(name) = self.session.config.clone() {
+            if config::Config::exists(&name) {
+                let c = self.conf_dialog.open_named(
+                    &name,
+                    &mut self.lua_plugin,
+                    &mut self.log,
+                    self.lua_log.clone(),
+                );
+                if let Some(c) = c {
+                    self.set_config(c);
+                }
+            }
+        }
+        if let Some(name) = self.session.plugin.clone() {
+            if self.config_file.website.as_ref() != Some(&name)
+                && self.plugins.iter().any(|(n, _)| *n == name)
+            {
+                self.run(Pending::SwitchPlugin(name), frame);
+            }
+        }
+    }
+
+    // Changes config through history, so the edit can be undone
+    fn edit_config<F>(&mut self, edit: F)
+    where
+        F: FnOnce(&mut config::Config) -> Result<(), String>,
+    {
+        if let Err(e) = self.history.edit(&mut self.config_file, edit) {
+            self.log.push_str(&format!("{}\n", e));
+        }
+    }
+
+    // Goes back to the saved state of config, or to defaults if it was never saved
+    fn revert_config(&mut self) {
+        let c = match self.conf_dialog.name() {
+            "" => {
+                let mut c = config::Config::default();
+                c.mark_clean();
+                c
+            }
+            name => {
+                let (c, issues) = config::Config::read(name, &mut self.log);
+                for i in issues {
+                    self.log.push_str(&format!("{}: {}\n", name, i));
+                }
+                c
+            }
+        };
+        self.set_config(c);
+    }
+
+    // Replaces config with loaded one, its history starts anew
+    fn set_config(&mut self, c: config::Config) {
+        self.config_file = c;
+        self.history.reset();
+    }
+
+    // Moves through undo history and brings plugin in line with config
+    fn history_go(&mut self, pos: usize) {
+        let website = self.config_file.website.clone();
+        if let Err(e) = self.history.go_to(pos, &mut self.config_file) {
+            self.log.push_str(&format!("{}\n", e));
+            return;
+        }
+        let name = self.config_file.website.clone();
+        if name != website {
+            let settings = name
+                .as_ref()
+                .and_then(|n| self.config_file.plugin_settings.get(n));
+            self.lua_plugin
+                .load(name.clone(), settings, &mut self.log, self.lua_log.clone());
+        } else if let Some(n) = name {
+            let values = plugin_settings::resolve(
+                self.lua_plugin.setting_decls(),
+                self.config_file.plugin_settings.get(&n),
+            );
+            self.lua_plugin.set_settings(values);
+        }
+    }
+
+    fn history_window(&mut self, ctx: &egui::CtxRef) {
+        let mut jump = None;
+        let history = &self.history;
+        egui::Window::new("History")
+            .open(&mut self.history_w)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for i in 0..=history.changes().len() {
+                        let mut label = match i {
+                            0 => "Opened config".to_owned(),
+                            _ => format!("Changed {}", history.changes()[i - 1].fields.join(", ")),
+                        };
+                        if history.saved() == Some(i) {
+                            label.push_str(" (saved)");
+                        }
+                        if ui.selectable_label(history.pos() == i, label).clicked() {
+                            jump = Some(i);
+                        }
+                    }
+                });
+            });
+        if let Some(i) = jump {
+            self.history_go(i);
+        }
+    }
+
+    fn update_title(&mut self, frame: &epi::Frame) {
+        let name = match self.conf_dialog.name() {
+            "" => "untitled",
+            n => n,
+        };
+        let mark = if self.config_file.is_dirty() {
+            " *"
+        } else {
+            ""
+        };
+        let title = format!("FlexAR Beta - {}{}", name, mark);
+        if title != self.title {
+            frame.set_window_title(&title);
+            self.title = title;
         }
     }
 }
@@ -39,10 +432,15 @@ impl epi::App for FlexApp {
     fn setup(
         &mut self,
         ctx: &egui::CtxRef,
-        _frame: &epi::Frame,
-        _storage: Option<&dyn epi::Storage>,
+        frame: &epi::Frame,
+        storage: Option<&dyn epi::Storage>,
     ) {
-        self.conf_dialog.load(&mut self.log);
+        self.conf_dialog.refresh(&mut self.log);
+        self.profile_dialog.refresh(&mut self.log);
+        self.settings = settings::Settings::load(&mut self.log);
+        self.lua_plugin.set_policy(self.settings.unsigned_plugins);
+        self.refresh_plugins();
+        self.restore_session(storage, frame);
 
         let mut font = egui::FontDefinitions::default();
 
@@ -56,48 +454,194 @@ impl epi::App for FlexApp {
             .insert(0, "font".to_owned());
         ctx.set_fonts(font);
 
-        let mut style = egui::Style::default();
-        let mut spacing = egui::style::Spacing::default();
-        spacing.item_spacing = egui::vec2(2., 5.);
-        style.spacing = spacing;
+        let style = egui::Style {
+            spacing: egui::style::Spacing {
+                item_spacing: egui::vec2(2., 5.),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
         ctx.set_style(style);
     }
 
+    fn save(&mut self, storage: &mut dyn epi::Storage) {
+        let name = self.conf_dialog.name();
+        self.session.config =
+            Some(name.to_owned()).filter(|n| !n.is_empty() && config::Config::exists(n));
+        self.session.plugin = self.config_file.website.clone();
+        session::store(storage, &self.session);
+    }
+
+    fn on_exit(&mut self) {
+        self.lua_plugin.shutdown(&mut self.log);
+    }
+
     fn update(&mut self, ctx: &egui::CtxRef, frame: &epi::Frame) {
         if self.about_w {
             dialogs::about(ctx, &mut self.about_w);
         }
-        let open = self.conf_dialog.show_open(ctx, &mut self.log);
+        self.plugin_doc.show(ctx);
+        self.resolve_pending(ctx, frame);
+        if !ctx.wants_keyboard_input() {
+            let (undo, redo) = {
+                let input = ctx.input();
+                let z = input.modifiers.command && input.key_pressed(egui::Key::Z);
+                (z && !input.modifiers.shift, z && input.modifiers.shift)
+            };
+            if undo && self.history.can_undo() {
+                self.history_go(self.history.pos() - 1);
+            }
+            if redo && self.history.can_redo() {
+                self.history_go(self.history.pos() + 1);
+            }
+        }
+        if self.history_w {
+            self.history_window(ctx);
+        }
+        if let Some(caps) = self.lua_plugin.pending_permissions() {
+            if let Some(allow) =
+                dialogs::permissions(ctx, self.lua_plugin.name(), self.lua_plugin.version(), caps)
+            {
+                self.lua_plugin
+                    .resolve_permissions(allow, &mut self.log, self.lua_log.clone());
+            }
+        }
+        let open = self.conf_dialog.show_open(
+            ctx,
+            &mut self.lua_plugin,
+            &mut self.log,
+            self.lua_log.clone(),
+        );
         if let Some(c) = open {
-            self.config_file = c;
-            self.lua_plugin.load(
-                self.config_file.website.clone(),
-                &mut self.log,
-                self.lua_log.clone(),
-            );
+            self.set_config(c);
         }
         self.conf_dialog
-            .show_save(ctx, &mut self.config_file, &mut self.log);
+            .show_save(ctx, &mut self.config_file, &self.lua_plugin, &mut self.log);
+        // these windows may add plugins, list is reread once they close
+        let windows = |app: &Self| {
+            [
+                app.unpack_dialog.is_open(),
+                app.plugin_browser.is_open(),
+                app.profile_dialog.is_open(),
+            ]
+        };
+        let was_open = windows(self);
+        self.unpack_dialog.show(ctx, &mut self.log);
+        if let Some(change) = self.install_dialog.show(ctx, &mut self.log) {
+            self.plugin_changed(change, frame);
+        }
+        if let Some(change) = self
+            .plugin_browser
+            .show(ctx, &mut self.settings, &mut self.log)
+        {
+            self.plugin_changed(change, frame);
+        }
+        match self.profile_dialog.show(ctx, &mut self.log) {
+            Some(dialogs::ProfileAction::Switch(name)) => {
+                self.request(Pending::SwitchProfile(name), frame)
+            }
+            Some(dialogs::ProfileAction::Save(name)) => {
+                let size = ctx.input().screen_rect().size();
+                self.save_profile(&name, size);
+            }
+            None => (),
+        }
+        if was_open
+            .iter()
+            .zip(windows(self))
+            .any(|(was, is)| *was && !is)
+        {
+            self.refresh_plugins();
+        }
 
         egui::TopBottomPanel::top("top").show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
                 ui.menu_button("File", |ui| {
                     if ui.button("Open").clicked() {
-                        self.conf_dialog.open = true;
+                        self.request(Pending::Open, frame);
                     };
                     if ui.button("Save").clicked() {
-                        self.conf_dialog.save = true;
+                        self.conf_dialog.save_dialog(&mut self.log);
+                    };
+                    if ui.button("Import...").clicked() {
+                        self.request(Pending::Import, frame);
+                    };
+                    if ui.button("Export...").clicked() {
+                        self.conf_dialog.export_dialog();
                     };
                     if ui.button("Unpack plugins").clicked() {
-                        let mut path = dirs::config_dir().unwrap();
-                        path.push("flexar/plugins/".to_owned());
-                        plugin::unpack_plugins(&path, plugin::PLUGINS.path(), &mut self.log);
-                        self.log.push_str("Default plugins was unpacked.\n")
+                        self.unpack_dialog.open_dialog(&mut self.log);
                     };
                     if ui.button("Exit").clicked() {
-                        frame.quit();
+                        self.request(Pending::Exit, frame);
                     };
                 });
+                ui.menu_button("Edit", |ui| {
+                    if ui
+                        .add_enabled(self.history.can_undo(), egui::Button::new("Undo"))
+                        .clicked()
+                    {
+                        self.history_go(self.history.pos() - 1);
+                    }
+                    if ui
+                        .add_enabled(self.history.can_redo(), egui::Button::new("Redo"))
+                        .clicked()
+                    {
+                        self.history_go(self.history.pos() + 1);
+                    }
+                    if ui.button("History").clicked() {
+                        self.history_w = true;
+                    }
+                });
+                ui.menu_button("Plugins", |ui| {
+                    if ui.button("Install from archive...").clicked() {
+                        self.install_dialog.install_dialog(false);
+                    }
+                    if ui.button("Update from archive...").clicked() {
+                        self.install_dialog.install_dialog(true);
+                    }
+                    if ui.button("Browse repository...").clicked() {
+                        self.plugin_browser.open_dialog(&self.settings);
+                    }
+                    let mut allow = self.settings.unsigned_plugins == integrity::Policy::Allow;
+                    if ui.checkbox(&mut allow, "Allow unsigned plugins").changed() {
+                        self.settings.unsigned_plugins = if allow {
+                            integrity::Policy::Allow
+                        } else {
+                            integrity::Policy::Refuse
+                        };
+                        self.lua_plugin.set_policy(self.settings.unsigned_plugins);
+                        if let Err(e) = self.settings.save() {
+                            self.log.push_str(&format!("settings: {}\n", e));
+                        }
+                    }
+                    ui.separator();
+                    ui.menu_button("Uninstall", |ui| {
+                        for (name, _) in &self.plugins {
+                            if ui.button(name).clicked() {
+                                self.install_dialog.uninstall_dialog(name.clone());
+                            }
+                        }
+                    });
+                });
+                ui.menu_button("Profiles", |ui| {
+                    let mut switch = None;
+                    for name in self.profile_dialog.profiles() {
+                        let current = self.session.profile.as_ref() == Some(name);
+                        if ui.selectable_label(current, name).clicked() {
+                            switch = Some(name.clone());
+                        }
+                    }
+                    if let Some(name) = switch {
+                        self.request(Pending::SwitchProfile(name), frame);
+                    }
+                    if !self.profile_dialog.profiles().is_empty() {
+                        ui.separator();
+                    }
+                    if ui.button("Manage...").clicked() {
+                        self.profile_dialog.open_dialog(&mut self.log);
+                    }
+                });
                 if ui
                     .button("About")
                     .on_hover_cursor(egui::CursorIcon::PointingHand)
@@ -112,46 +656,148 @@ impl epi::App for FlexApp {
                     ui.heading("Flexar setup");
                     ui.horizontal(|ui| {
                         ui.label("Plugin: ");
-                        let pname = if let Some(pname) = &self.config_file.website {
-                            pname
-                        } else {
-                            "None"
+                        let pname = match &self.config_file.website {
+                            Some(pname) if self.lua_plugin.name() == pname => self
+                                .lua_plugin
+                                .info()
+                                .map_or(pname.clone(), |i| i.title(pname)),
+                            Some(pname) => pname.clone(),
+                            None => "None".to_owned(),
                         };
                         egui::ComboBox::from_id_source("Plugin sel")
-                            .selected_text(format!("{:?}", pname))
+                            .selected_text(pname)
                             .show_ui(ui, |ui| {
-                                let list = plugin::get_list(&mut self.log);
-                                for name in list {
+                                let mut chosen = None;
+                                for (name, info) in &self.plugins {
+                                    let mut hover = name.clone();
+                                    if !info.description.is_empty() {
+                                        hover = format!("{}\n{}", hover, info.description);
+                                    }
                                     if ui
                                         .add(egui::SelectableLabel::new(
-                                            self.config_file.website == Some(name.clone()),
-                                            &name,
+                                            self.config_file.website.as_ref() == Some(name),
+                                            info.title(name),
                                         ))
+                                        .on_hover_text(hover)
                                         .clicked()
                                     {
-                                        self.config_file.website = Some(name.clone());
-                                        self.lua_plugin.load(
-                                            self.config_file.website.clone(),
-                                            &mut self.log,
-                                            self.lua_log.clone(),
-                                        );
+                                        chosen = Some(name.clone());
+                                    }
+                                }
+                                if let Some(name) = chosen {
+                                    if self.config_file.website.as_deref() == Some(&name) {
+                                        // reload of the same plugin keeps config intact
+                                        self.run(Pending::SwitchPlugin(name), frame);
+                                    } else {
+                                        self.request(Pending::SwitchPlugin(name), frame);
                                     }
                                 }
                             });
+                        if let Some(status) = self.lua_plugin.integrity() {
+                            let color = match status {
+                                integrity::Status::Verified(_) => egui::Color32::GREEN,
+                                integrity::Status::Unsigned => egui::Color32::GRAY,
+                                integrity::Status::Tampered(_) => egui::Color32::RED,
+                            };
+                            ui.colored_label(color, status.label());
+                        }
                     });
 
-                    ui.horizontal(|ui| {
-                        ui.label("Number of accounts: ");
-                        ui.add(egui::DragValue::new(&mut self.config_file.reg_num));
-                    });
+                    if self.config_file.website.is_some() {
+                        let r = egui::CollapsingHeader::new("Plugin info")
+                            .open(Some(self.session.info_open))
+                            .show(ui, |ui| {
+                                if dialogs::plugin_info(ui, &self.lua_plugin) {
+                                    self.plugin_doc.open_doc(&self.lua_plugin);
+                                }
+                            });
+                        if r.header_response.clicked() {
+                            self.session.info_open = !self.session.info_open;
+                        }
+                    }
+
+                    if !self.lua_plugin.setting_decls().is_empty() {
+                        let r = egui::CollapsingHeader::new("Plugin settings")
+                            .open(Some(self.session.settings_open))
+                            .show(ui, |ui| {
+                                let mut values = self.lua_plugin.settings();
+                                if plugin_settings::show(
+                                    ui,
+                                    self.lua_plugin.setting_decls(),
+                                    &mut values,
+                                ) {
+                                    let name = self.lua_plugin.name().to_owned();
+                                    let v = values.clone();
+                                    if let Err(e) = self.history.edit(&mut self.config_file, |c| {
+                                        c.set_plugin_settings(&name, v);
+                                        Ok(())
+                                    }) {
+                                        self.log.push_str(&format!("{}\n", e));
+                                    }
+                                    self.lua_plugin.set_settings(values);
+                                }
+                            });
+                        if r.header_response.clicked() {
+                            self.session.settings_open = !self.session.settings_open;
+                        }
+                    }
+
+                    let r = egui::CollapsingHeader::new("Config")
+                        .open(Some(self.session.config_open))
+                        .show(ui, |ui| {
+                            egui::ScrollArea::vertical()
+                                .id_source("config_scroll")
+                                .max_height(200.)
+                                .show(ui, |ui| {
+                                    let mut table = match self.config_file.to_table() {
+                                        Ok(t) => t,
+                                        Err(e) => {
+                                            ui.colored_label(egui::Color32::RED, e);
+                                            return;
+                                        }
+                                    };
+                                    let changed = form::show(
+                                        ui,
+                                        &self.schema,
+                                        &mut table,
+                                        &config::FORM_HINTS,
+                                    );
+                                    if !changed.is_empty() {
+                                        if let Err(e) =
+                                            self.history.edit(&mut self.config_file, |c| {
+                                                c.set_fields(&changed, &table)
+                                            })
+                                        {
+                                            self.log.push_str(&format!("{}\n", e));
+                                        }
+                                    }
+                                });
+                        });
+                    if r.header_response.clicked() {
+                        self.session.config_open = !self.session.config_open;
+                    }
 
                     ui.label(format!("Registered: {}", self.reg_count));
 
-                    egui::ScrollArea::vertical()
-                        .max_height(120.)
+                    let r = egui::CollapsingHeader::new("Log")
+                        .open(Some(self.session.log_open))
                         .show(ui, |ui| {
-                            ui.add_enabled(false, egui::TextEdit::multiline(&mut self.log));
+                            let filter = &mut self.session.log_filter;
+                            ui.horizontal(|ui| {
+                                ui.label("Filter: ");
+                                ui.text_edit_singleline(&mut filter.text);
+                                ui.checkbox(&mut filter.case_sensitive, "Match case");
+                            });
+                            let mut shown = filter.apply(&self.log);
+                            egui::ScrollArea::vertical()
+                                .max_height(120.)
+                                .show(ui, |ui| {
+                                    ui.add_enabled(false, egui::TextEdit::multiline(&mut shown));
+                                });
                         });
+                    if r.header_response.clicked() {
+                        self.session.log_open = !self.session.log_open;
+                    }
 
                     ui.add(egui::Button::new("Start"));
                     ui.add(egui::Button::new("Stop"));
@@ -159,14 +805,15 @@ impl epi::App for FlexApp {
             );
         });
 
-        match self
-            .lua_plugin
-            .call_draw(ctx.clone(), &self.lua_log, &mut self.log)
-        {
+        match self.lua_plugin.call_draw(
+            plugin_ui::Target::Screen(ctx.clone()),
+            &self.lua_log,
+            &mut self.log,
+        ) {
             Ok(_) => (),
             Err(_) => self
                 .lua_plugin
-                .load(None, &mut self.log, self.lua_log.clone()),
+                .load(None, None, &mut self.log, self.lua_log.clone()),
         };
 
         egui::TopBottomPanel::bottom("bottom").show(ctx, |ui| {
@@ -180,13 +827,31 @@ impl epi::App for FlexApp {
 
         self.log.push_str(&format!("{}", self.lua_log.borrow()));
         *self.lua_log.borrow_mut() = String::new();
+        if !self.config_file.is_dirty() {
+            self.history.mark_saved();
+        }
+        self.update_title(frame);
     }
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args = match cli::init_paths(args) {
+        Ok(a) => a,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    if let Some(code) = cli::run(&args) {
+        std::process::exit(code);
+    }
+
     let app = FlexApp::default();
-    let mut native_options = eframe::NativeOptions::default();
-    native_options.initial_window_size = Some(egui::vec2(300., 480.));
-    native_options.resizable = false;
+    let native_options = eframe::NativeOptions {
+        initial_window_size: Some(egui::vec2(300., 480.)),
+        resizable: true,
+        ..Default::default()
+    };
     eframe::run_native(Box::new(app), native_options);
 }
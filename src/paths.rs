@@ -0,0 +1,180 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+// Environment variable overriding data directory
+pub const HOME_VAR: &str = "FLEXAR_HOME";
+// Marker file next to the executable which turns on portable mode
+pub const PORTABLE_MARKER: &str = "portable";
+
+static PATHS: OnceLock<Paths> = OnceLock::new();
+
+// Locations of everything flexar stores on disk
+#[derive(Debug, Clone)]
+pub struct Paths {
+    root: PathBuf,
+}
+
+impl Paths {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    // Root is taken from --data-dir, portable mode, FLEXAR_HOME or platform config dir
+    pub fn resolve(data_dir: Option<PathBuf>, portable: bool) -> Self {
+        let exe_dir = std::env::current_exe()
+            .ok()
+            .and_then(|e| e.parent().map(Path::to_path_buf));
+        Paths::choose(data_dir, portable, exe_dir, std::env::var_os(HOME_VAR))
+    }
+
+    // Same as resolve with executable location and FLEXAR_HOME already read
+    fn choose(
+        data_dir: Option<PathBuf>,
+        portable: bool,
+        exe_dir: Option<PathBuf>,
+        home: Option<OsString>,
+    ) -> Self {
+        if let Some(dir) = data_dir {
+            return Paths::new(dir);
+        }
+        if let Some(exe_dir) = &exe_dir {
+            if portable || exe_dir.join(PORTABLE_MARKER).exists() {
+                return Paths::new(exe_dir.join("flexar-data"));
+            }
+        }
+        if let Some(dir) = home.filter(|d| !d.is_empty()) {
+            return Paths::new(PathBuf::from(dir));
+        }
+        if let Some(dir) = dirs::config_dir() {
+            return Paths::new(dir.join("flexar"));
+        }
+        if let Some(dir) = dirs::home_dir() {
+            return Paths::new(dir.join(".flexar"));
+        }
+        Paths::new(exe_dir.unwrap_or_default().join("flexar"))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn configs(&self) -> PathBuf {
+        self.root.join("configs")
+    }
+
+    pub fn config_file(&self, name: &str) -> PathBuf {
+        self.configs().join(format!("{}.toml", name))
+    }
+
+    pub fn config_backups(&self) -> PathBuf {
+        self.configs().join("backups")
+    }
+
+    pub fn profiles(&self) -> PathBuf {
+        self.root.join("profiles")
+    }
+
+    pub fn profile_file(&self, name: &str) -> PathBuf {
+        self.profiles().join(format!("{}.toml", name))
+    }
+
+    pub fn plugins(&self) -> PathBuf {
+        self.root.join("plugins")
+    }
+
+    pub fn plugin(&self, name: &str) -> PathBuf {
+        self.plugins().join(name)
+    }
+
+    // Directory plugin may write to through fs API
+    pub fn plugin_data(&self, name: &str) -> PathBuf {
+        self.root.join("data").join(name)
+    }
+
+    pub fn settings_file(&self) -> PathBuf {
+        self.root.join("settings.toml")
+    }
+
+    // Archives fetched from plugin repository
+    pub fn downloads(&self) -> PathBuf {
+        self.root.join("downloads")
+    }
+
+    // Public keys plugin signatures are checked against, one *.pub file each
+    pub fn trusted_keys(&self) -> PathBuf {
+        self.root.join("trusted_keys")
+    }
+
+    pub fn permissions_file(&self) -> PathBuf {
+        self.root.join("permissions.toml")
+    }
+}
+
+// Sets paths for the whole process. Has no effect if paths are already in use.
+pub fn init(paths: Paths) {
+    let _ = PATHS.set(paths);
+}
+
+pub fn get() -> &'static Paths {
+    PATHS.get_or_init(default)
+}
+
+#[cfg(not(test))]
+fn default() -> Paths {
+    Paths::resolve(None, false)
+}
+
+// Tests share one temporary root and keep to their own names inside it
+#[cfg(test)]
+fn default() -> Paths {
+    let root = std::env::temp_dir().join(format!("flexar-tests-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    Paths::new(root)
+}
+
+// Empty directory for a test inside the shared root
+#[cfg(test)]
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = get().root().join("tmp").join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_root_in_order() {
+        let exe = test_dir("paths-exe");
+        let home = || Some(OsString::from("/home-var"));
+        let choose = |data_dir: Option<&str>, portable, home| {
+            Paths::choose(
+                data_dir.map(PathBuf::from),
+                portable,
+                Some(exe.clone()),
+                home,
+            )
+            .root()
+            .to_path_buf()
+        };
+        let platform = dirs::config_dir().map(|d| d.join("flexar"));
+
+        assert_eq!(choose(Some("/given"), true, home()), Path::new("/given"));
+        assert_eq!(choose(None, true, home()), exe.join("flexar-data"));
+        assert_eq!(choose(None, false, home()), Path::new("/home-var"));
+        if let Some(platform) = &platform {
+            assert_eq!(&choose(None, false, None), platform);
+            // empty variable counts as unset
+            assert_eq!(&choose(None, false, Some(OsString::new())), platform);
+        }
+
+        // marker next to executable turns on portable mode
+        std::fs::write(exe.join(PORTABLE_MARKER), "").unwrap();
+        assert_eq!(choose(None, false, home()), exe.join("flexar-data"));
+    }
+}
@@ -0,0 +1,166 @@
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{backup, paths};
+
+// Capabilities plugin can request in `permissions` field of config.toml
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Capability {
+    Fs,
+}
+
+impl Capability {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::Fs => "fs",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Capability::Fs => "Read files of the plugin and read/write its data directory",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Capability> {
+        match name {
+            "fs" => Some(Capability::Fs),
+            _ => None,
+        }
+    }
+}
+
+// User decision remembered for particular plugin version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Decision {
+    version: String,
+    granted: Vec<Capability>,
+}
+
+// Set of capabilities granted to loaded plugin, shared with lua callbacks
+#[derive(Debug, Clone, Default)]
+pub struct Grants(Rc<RefCell<Vec<Capability>>>);
+
+impl Grants {
+    pub fn new(granted: Vec<Capability>) -> Self {
+        Self(Rc::new(RefCell::new(granted)))
+    }
+
+    pub fn list(&self) -> Vec<Capability> {
+        self.0.borrow().clone()
+    }
+
+    pub fn allows(&self, cap: Capability) -> bool {
+        self.0.borrow().contains(&cap)
+    }
+
+    // Fails lua call if capability wasn't granted
+    pub fn check(&self, cap: Capability, api: &str) -> mlua::Result<()> {
+        if self.allows(cap) {
+            Ok(())
+        } else {
+            Err(mlua::Error::RuntimeError(format!(
+                "{}: permission denied, plugin wasn't granted the '{}' capability",
+                api,
+                cap.name()
+            )))
+        }
+    }
+}
+
+fn store_path() -> std::path::PathBuf {
+    paths::get().permissions_file()
+}
+
+fn read_store(log: &mut String) -> BTreeMap<String, Decision> {
+    let path = store_path();
+    if !path.exists() {
+        return BTreeMap::new();
+    }
+    let data = match std::fs::read_to_string(path) {
+        Ok(d) => d,
+        Err(e) => {
+            log.push_str(&format!("{:?}\n", e));
+            return BTreeMap::new();
+        }
+    };
+    match toml::from_str(&data) {
+        Ok(s) => s,
+        Err(e) => {
+            log.push_str(&format!("permissions.toml: {}\n", e));
+            BTreeMap::new()
+        }
+    }
+}
+
+// Returns remembered grants if user already decided for this plugin version
+pub fn stored(plugin: &str, version: &str, log: &mut String) -> Option<Vec<Capability>> {
+    read_store(log)
+        .remove(plugin)
+        .filter(|d| d.version == version)
+        .map(|d| d.granted)
+}
+
+pub fn remember(plugin: &str, version: &str, granted: &[Capability], log: &mut String) {
+    let mut store = read_store(log);
+    store.insert(
+        plugin.to_owned(),
+        Decision {
+            version: version.to_owned(),
+            granted: granted.to_vec(),
+        },
+    );
+    let data = match toml::to_string(&store) {
+        Ok(d) => d,
+        Err(e) => {
+            log.push_str(&format!("{:?}\n", e));
+            return;
+        }
+    };
+    let path = store_path();
+    if let Some(parent) = path.parent() {
+        if let Err(e) = std::fs::create_dir_all(parent) {
+            log.push_str(&format!("{}: {}\n", parent.display(), e));
+            return;
+        }
+    }
+    if let Err(e) = backup::write_atomic(&path, data.as_bytes()) {
+        log.push_str(&format!("{}: {}\n", path.display(), e));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remembers_decision_per_version() {
+        let mut log = String::new();
+        remember("perm-test", "1.0", &[Capability::Fs], &mut log);
+        assert_eq!(
+            stored("perm-test", "1.0", &mut log),
+            Some(vec![Capability::Fs])
+        );
+        // new version asks again
+        assert_eq!(stored("perm-test", "1.1", &mut log), None);
+
+        // denial is remembered too and replaces older decision
+        remember("perm-test", "1.1", &[], &mut log);
+        assert_eq!(stored("perm-test", "1.1", &mut log), Some(vec![]));
+        assert_eq!(stored("perm-test", "1.0", &mut log), None);
+        assert!(log.is_empty(), "{}", log);
+    }
+
+    #[test]
+    fn denies_capability_not_granted() {
+        let err = Grants::new(Vec::new())
+            .check(Capability::Fs, "fs.read")
+            .unwrap_err();
+        assert!(err.to_string().contains("permission denied"), "{}", err);
+        assert!(Grants::new(vec![Capability::Fs])
+            .check(Capability::Fs, "fs.read")
+            .is_ok());
+    }
+}
@@ -1,31 +1,201 @@
-use crate::{config::RegMethod, plugin_ui};
+use crate::{
+    config::RegMethod,
+    integrity::{self, Status},
+    lua_codec, paths,
+    permissions::{self, Capability},
+    plugin_fs, plugin_settings, plugin_ui, unpack,
+    version::{Range, Version},
+};
 
-use eframe::egui::CtxRef;
-use include_dir::DirEntry::{Dir, File};
-use mlua::{Function, Lua, LuaOptions, StdLib, Variadic};
+use mlua::{Function, Lua, LuaOptions, StdLib, ToLuaMulti, Variadic};
+use serde_derive::Deserialize;
 
 use std::{
     cell::RefCell,
-    io::{Read, Write},
+    io::Read,
+    path::{Component, Path, PathBuf},
     rc::Rc,
 };
 
 pub static PLUGINS: include_dir::Dir =
     include_dir::include_dir!("$CARGO_MANIFEST_DIR/assets/plugins");
 
-struct Config {
-    name: String,
+pub const HOST_VERSION: &str = env!("CARGO_PKG_VERSION");
+// Version of the Lua API. Minor is raised when API grows, major when
+// something is removed or changes meaning.
+pub const API_VERSION: &str = "1.1.0";
+
+// Globals plugins may still define, with replacements and API version
+// they were deprecated in
+const DEPRECATED: &[(&str, &str, &str)] = &[("load", "on_load", "1.1")];
+// Host functions still reachable under old names, as dotted paths from the
+// globals, with replacements and API version they were deprecated in
+const DEPRECATED_CALLS: &[(&str, &str, &str)] = &[];
+// Starts log lines that aren't errors
+pub const WARNING: &str = "Warning: ";
+
+// Describes plugin to the user, part of the manifest
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Info {
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub description: String,
+    // Documentation file relative to plugin directory
+    #[serde(default)]
+    pub homepage: String,
+    #[serde(default)]
+    pub min_host_version: String,
+    // Supported API versions, see version::Range
+    #[serde(default)]
+    pub api_version: String,
+}
+
+impl Info {
+    // Problems with field values, plugin may still suit another host
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if !self.min_host_version.is_empty() {
+            if let Err(e) = Version::parse(&self.min_host_version) {
+                errors.push(format!("config.toml: min_host_version: {}", e));
+            }
+        }
+        if !self.api_version.is_empty() {
+            if let Err(e) = Range::parse(&self.api_version) {
+                errors.push(format!("config.toml: api_version: {}", e));
+            }
+        }
+        if let Err(e) = self.check_homepage() {
+            errors.push(format!("config.toml: {}", e));
+        }
+        errors
+    }
+
+    fn check_homepage(&self) -> Result<(), String> {
+        if Path::new(&self.homepage)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            Ok(())
+        } else {
+            Err(format!(
+                "homepage: {} is not inside plugin directory",
+                self.homepage
+            ))
+        }
+    }
+
+    // Documentation file, refused if it leads outside of plugin directory,
+    // manifests of loaded plugins aren't validated
+    pub fn doc_path(&self, dir: &Path) -> Result<PathBuf, String> {
+        self.check_homepage()?;
+        let path = dir.join(&self.homepage);
+        let real = path
+            .canonicalize()
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let root = dir.canonicalize().map_err(|e| e.to_string())?;
+        if !real.starts_with(root) {
+            return Err(format!(
+                "homepage: {} is not inside plugin directory",
+                self.homepage
+            ));
+        }
+        Ok(real)
+    }
+
+    // Name with version for plugin lists, directory name if manifest has none
+    pub fn title(&self, dir_name: &str) -> String {
+        let name = if self.name.is_empty() {
+            dir_name
+        } else {
+            &self.name
+        };
+        if self.version.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{} {}", name, self.version)
+        }
+    }
+
+    // Fails if plugin can't run on this host
+    pub fn check(&self) -> Result<(), String> {
+        if !self.min_host_version.is_empty() {
+            let min = Version::parse(&self.min_host_version)?;
+            let host = Version::parse(HOST_VERSION)?;
+            if host < min {
+                return Err(format!(
+                    "it needs Flexar {} or newer, this is {}",
+                    min, host
+                ));
+            }
+        }
+        if !self.api_version.is_empty() {
+            let range = Range::parse(&self.api_version)?;
+            let api = Version::parse(API_VERSION)?;
+            if !range.contains(api) {
+                return Err(format!(
+                    "it supports plugin API {}, this host provides {}",
+                    range, api
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Plugin manifest (config.toml)
+// Registration fields are not consumed by the host yet
+#[allow(dead_code)]
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(flatten)]
+    info: Info,
     use_photo: bool,
     use_status_files: bool,
     use_subscribes_files: bool,
     use_posts_files: bool,
     reg_methods: Vec<RegMethod>,
+    // Capability names, unknown ones are reported instead of failing the parse
+    #[serde(default)]
+    permissions: Vec<String>,
+    #[serde(default)]
+    settings: Vec<plugin_settings::Setting>,
+}
+
+impl Manifest {
+    fn capabilities(&self) -> Result<Vec<Capability>, String> {
+        self.permissions
+            .iter()
+            .map(|n| {
+                Capability::from_name(n).ok_or(format!(
+                    "config.toml: unknown capability `{}` in permissions",
+                    n
+                ))
+            })
+            .collect()
+    }
 }
 
 pub struct Plugin {
     name: String,
     lua: Option<&'static Lua>,
     src: String,
+    dir: PathBuf,
+    data_dir: PathBuf,
+    manifest: Option<Manifest>,
+    settings: Rc<RefCell<toml::value::Table>>,
+    grants: permissions::Grants,
+    // Capabilities waiting for user approval before plugin is started
+    pending: Option<Vec<Capability>>,
+    integrity: Option<integrity::Status>,
+    policy: integrity::Policy,
+    // Last error line plugin added to the log
+    last_error: RefCell<Option<String>>,
+    // Raised inside lua calls, moved to the log once the call returns
+    warnings: Rc<RefCell<String>>,
 }
 
 impl Plugin {
@@ -34,20 +204,90 @@ impl Plugin {
             name: String::new(),
             lua: None,
             src: String::new(),
+            dir: PathBuf::new(),
+            data_dir: PathBuf::new(),
+            manifest: None,
+            settings: Rc::new(RefCell::new(toml::value::Table::new())),
+            grants: permissions::Grants::default(),
+            pending: None,
+            integrity: None,
+            policy: integrity::Policy::default(),
+            last_error: RefCell::new(None),
+            warnings: Rc::new(RefCell::new(String::new())),
         }
     }
 
     // Загрузка плагина и получение chunk'а для выполнения плагина
-    pub fn load(&mut self, name: Option<String>, log: &mut String, lua_log: Rc<RefCell<String>>) {
-        let sname;
-        if let Some(n) = name {
-            sname = n
+    pub fn load(
+        &mut self,
+        name: Option<String>,
+        settings: Option<&toml::value::Table>,
+        log: &mut String,
+        lua_log: Rc<RefCell<String>>,
+    ) {
+        self.unload(log);
+        let sname = if let Some(n) = name {
+            n
         } else {
-            self.lua = None;
-            sname = "None".to_owned();
+            self.name.clear();
+            self.manifest = None;
+            self.integrity = None;
+            *self.last_error.borrow_mut() = None;
             return;
+        };
+
+        let dir = paths::get().plugin(&sname);
+        let data_dir = paths::get().plugin_data(&sname);
+
+        let start = log.len();
+        if self.load_dir(sname, dir, data_dir, settings, log) {
+            self.setup_base(lua_log, log);
+            self.call_load(log);
         }
+        self.note_error(log, start);
+    }
 
+    // Загрузка плагина из каталога. Возвращает false, если плагин
+    // не может быть запущен или ждёт подтверждения разрешений.
+    pub fn load_dir(
+        &mut self,
+        name: String,
+        dir: PathBuf,
+        data_dir: PathBuf,
+        settings: Option<&toml::value::Table>,
+        log: &mut String,
+    ) -> bool {
+        self.unload(log);
+        *self.last_error.borrow_mut() = None;
+        let manifest = Plugin::load_manifest(&dir, log);
+        let status = integrity::check(&dir, &integrity::trusted_keys(log));
+        let refuse = match &status {
+            Status::Tampered(reason) => Some(format!("its files were altered ({})", reason)),
+            Status::Unsigned if self.policy == integrity::Policy::Refuse => {
+                Some("it is unsigned and unsigned plugins are not allowed".to_owned())
+            }
+            // without manifest requested permissions are unknown
+            _ => match &manifest {
+                None => Some("its manifest config.toml can't be read".to_owned()),
+                Some(m) => m.capabilities().err().or_else(|| m.info.check().err()),
+            },
+        };
+        self.integrity = Some(status);
+        if let Some(reason) = refuse {
+            log.push_str(&format!("Plugin {} was not loaded: {}.\n", name, reason));
+            self.name = name;
+            self.dir = dir;
+            self.manifest = manifest;
+            return false;
+        }
+        if let Some(m) = manifest.as_ref().filter(|m| m.info.api_version.is_empty()) {
+            log.push_str(&format!(
+                "{}plugin {} doesn't declare api_version, this host provides API {}.\n",
+                WARNING,
+                m.info.title(&name),
+                API_VERSION
+            ));
+        }
         let lua = match Lua::new_with(
             StdLib::MATH | StdLib::STRING | StdLib::UTF8 | StdLib::TABLE | StdLib::PACKAGE,
             LuaOptions::default(),
@@ -55,18 +295,131 @@ impl Plugin {
             Ok(lua) => lua.into_static(),
             Err(e) => {
                 log.push_str(&format!("{:?}\n", e));
-                return;
+                return false;
             }
         };
 
-        let src = Plugin::load_plugin_file(&sname, log);
+        let src = Plugin::load_plugin_file(&dir, log);
+        let values =
+            plugin_settings::resolve(manifest.as_ref().map_or(&[], |m| &m.settings[..]), settings);
 
-        self.name = sname;
+        self.name = name;
+        self.dir = dir;
+        self.data_dir = data_dir;
         self.lua = Some(lua);
         self.src = src;
+        self.manifest = manifest;
+        self.settings = Rc::new(RefCell::new(values));
+        self.pending = None;
+
+        let (version, requested) = match &self.manifest {
+            Some(m) => (m.info.version.clone(), m.capabilities().unwrap_or_default()),
+            None => (String::new(), Vec::new()),
+        };
+        if requested.is_empty() {
+            self.grants = permissions::Grants::default();
+        } else {
+            match permissions::stored(&self.name, &version, log) {
+                Some(granted) => self.grants = permissions::Grants::new(granted),
+                None => {
+                    self.pending = Some(requested);
+                    return false;
+                }
+            }
+        }
+        true
+    }
 
+    pub fn info(&self) -> Option<&Info> {
+        self.manifest.as_ref().map(|m| &m.info)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn load_status(&self) -> &'static str {
+        if self.name.is_empty() {
+            "not loaded"
+        } else if self.pending.is_some() {
+            "waiting for permissions"
+        } else if self.lua.is_some() {
+            "running"
+        } else {
+            "stopped"
+        }
+    }
+
+    pub fn last_error(&self) -> Option<String> {
+        self.last_error.borrow().clone()
+    }
+
+    // Remembers last error line added to log since start
+    fn note_error(&self, log: &str, start: usize) {
+        let added = log.get(start..).unwrap_or_default();
+        if let Some(line) = added
+            .lines()
+            .rev()
+            .find(|l| !l.trim().is_empty() && !l.starts_with(WARNING))
+        {
+            *self.last_error.borrow_mut() = Some(line.to_owned());
+        }
+    }
+
+    // Moves warnings raised by lua calls to the log
+    fn flush_warnings(&self, log: &mut String) {
+        log.push_str(&std::mem::take(&mut *self.warnings.borrow_mut()));
+    }
+
+    pub fn granted(&self) -> Vec<Capability> {
+        self.grants.list()
+    }
+
+    pub fn integrity(&self) -> Option<&integrity::Status> {
+        self.integrity.as_ref()
+    }
+
+    pub fn set_policy(&mut self, policy: integrity::Policy) {
+        self.policy = policy;
+    }
+
+    pub fn pending_permissions(&self) -> Option<&[Capability]> {
+        self.pending.as_deref()
+    }
+
+    pub fn version(&self) -> &str {
+        self.manifest.as_ref().map_or("", |m| &m.info.version)
+    }
+
+    // Применение решения пользователя и запуск плагина, ожидавшего подтверждения
+    pub fn resolve_permissions(
+        &mut self,
+        allow: bool,
+        log: &mut String,
+        lua_log: Rc<RefCell<String>>,
+    ) {
+        let requested = match self.pending.take() {
+            Some(r) => r,
+            None => return,
+        };
+        let granted = if allow { requested } else { Vec::new() };
+        permissions::remember(&self.name, self.version(), &granted, log);
+        self.start(granted, log, lua_log);
+    }
+
+    // Запуск загруженного плагина с указанными разрешениями
+    pub fn start(
+        &mut self,
+        granted: Vec<Capability>,
+        log: &mut String,
+        lua_log: Rc<RefCell<String>>,
+    ) {
+        self.pending = None;
+        self.grants = permissions::Grants::new(granted);
+        let start = log.len();
         self.setup_base(lua_log, log);
         self.call_load(log);
+        self.note_error(log, start);
     }
 
     fn setup_base(&mut self, lua_log: Rc<RefCell<String>>, log: &mut String) {
@@ -92,11 +445,148 @@ impl Plugin {
             Ok(_) => (),
             Err(e) => log.push_str(&format!("{:?}", e)),
         };
+
+        let flexar = match lua.create_table() {
+            Ok(t) => t,
+            Err(e) => {
+                log.push_str(&format!("{:?}", e));
+                return;
+            }
+        };
+        let grants = self.grants.clone();
+        match lua.create_function(move |_, name: String| {
+            Ok(Capability::from_name(&name).is_some_and(|c| grants.allows(c)))
+        }) {
+            Ok(f) => match flexar.set("has_permission", f) {
+                Ok(_) => (),
+                Err(e) => log.push_str(&format!("{:?}", e)),
+            },
+            Err(e) => log.push_str(&format!("{:?}", e)),
+        };
+        match flexar.set("api_version", API_VERSION) {
+            Ok(_) => (),
+            Err(e) => log.push_str(&format!("{:?}", e)),
+        };
+        match globals.set("flexar", flexar) {
+            Ok(_) => (),
+            Err(e) => log.push_str(&format!("{:?}", e)),
+        };
+
+        match plugin_fs::create_api(
+            lua,
+            plugin_fs::Sandbox::new(self.dir.clone(), self.data_dir.clone()),
+            self.grants.clone(),
+        ) {
+            Ok(fs) => match globals.set("fs", fs) {
+                Ok(_) => (),
+                Err(e) => log.push_str(&format!("{:?}", e)),
+            },
+            Err(e) => log.push_str(&format!("{:?}", e)),
+        };
+
+        match lua_codec::create_toml_api(lua) {
+            Ok(t) => match globals.set("toml", t) {
+                Ok(_) => (),
+                Err(e) => log.push_str(&format!("{:?}", e)),
+            },
+            Err(e) => log.push_str(&format!("{:?}", e)),
+        };
+        match lua_codec::create_json_api(lua) {
+            Ok(t) => match globals.set("json", t) {
+                Ok(_) => (),
+                Err(e) => log.push_str(&format!("{:?}", e)),
+            },
+            Err(e) => log.push_str(&format!("{:?}", e)),
+        };
+
+        match globals.set(
+            "settings",
+            plugin_settings::SettingsView(self.settings.clone()),
+        ) {
+            Ok(_) => (),
+            Err(e) => log.push_str(&format!("{:?}", e)),
+        };
+
+        self.warnings.borrow_mut().clear();
+        match alias_deprecated(lua, DEPRECATED_CALLS, &self.name, self.warnings.clone()) {
+            Ok(_) => (),
+            Err(e) => log.push_str(&format!("{:?}", e)),
+        };
+    }
+
+    // Выгрузка текущего плагина перед сменой
+    fn unload(&mut self, log: &mut String) {
+        self.call_hook("on_unload", (), log);
+        self.lua = None;
+        self.pending = None;
+    }
+
+    // Завершение работы плагина при закрытии приложения
+    pub fn shutdown(&mut self, log: &mut String) {
+        self.call_hook("on_shutdown", (), log);
+        self.lua = None;
+        self.pending = None;
+    }
+
+    pub fn config_loaded(&self, name: &str, log: &mut String) {
+        self.call_hook("on_config_loaded", name.to_owned(), log);
+    }
+
+    pub fn config_saved(&self, name: &str, log: &mut String) {
+        self.call_hook("on_config_saved", name.to_owned(), log);
+    }
+
+    // Вызов необязательного обработчика событий, ошибки только пишутся в лог
+    fn call_hook<A: ToLuaMulti<'static>>(&self, hook: &str, args: A, log: &mut String) {
+        let lua = match self.lua {
+            Some(lua) if self.pending.is_none() => lua,
+            _ => return,
+        };
+
+        let start = log.len();
+        match lua.globals().get::<_, Option<Function>>(hook) {
+            Ok(Some(f)) => f.call(args).unwrap_or_else(|e| {
+                log.push_str(&format!("{:?}\n", e));
+            }),
+            Ok(None) => (),
+            Err(e) => log.push_str(&format!("{}: {:?}\n", hook, e)),
+        }
+        self.flush_warnings(log);
+        self.note_error(log, start);
+    }
+
+    pub fn lua(&self) -> Option<&'static Lua> {
+        self.lua
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn setting_decls(&self) -> &[plugin_settings::Setting] {
+        match &self.manifest {
+            Some(m) if self.lua.is_some() => &m.settings,
+            _ => &[],
+        }
+    }
+
+    pub fn settings(&self) -> toml::value::Table {
+        self.settings.borrow().clone()
+    }
+
+    pub fn set_settings(&mut self, values: toml::value::Table) {
+        *self.settings.borrow_mut() = values;
     }
 
     fn call_load(&mut self, log: &mut String) {
         let lua = self.lua.as_ref().unwrap();
-        println!("{}", &self.src);
+        let globals = lua.globals();
+        // deprecated names may clash with builtins, like `load`
+        let builtins: Vec<Option<Function>> = DEPRECATED
+            .iter()
+            .map(|(old, _, _)| globals.get(*old).ok())
+            .collect();
+
         let chunk = lua.load(&self.src);
 
         match chunk.exec() {
@@ -104,60 +594,96 @@ impl Plugin {
             Err(e) => log.push_str(&format!("{:?}\n", e)),
         };
 
-        let globals = lua.globals();
+        let mut legacy_load = None;
+        for ((old, new, since), builtin) in DEPRECATED.iter().zip(builtins) {
+            let defined = globals
+                .get::<_, Option<Function>>(*old)
+                .ok()
+                .flatten()
+                .filter(|f| Some(f) != builtin.as_ref());
+            if defined.is_some() {
+                log.push_str(&format!(
+                    "{}plugin {} uses `{}`, deprecated since API {}, use `{}` instead.\n",
+                    WARNING, self.name, old, since, new
+                ));
+                if *old == "load" {
+                    legacy_load = defined;
+                }
+            }
+        }
 
-        let load: Option<Function> = match globals.get("load") {
-            Ok(v) => Some(v),
+        let on_load = match globals.get::<_, Option<Function>>("on_load") {
+            Ok(f) => f.or(legacy_load),
             Err(e) => {
                 log.push_str(&format!("{:?}\n", e));
                 None
             }
         };
 
-        match load {
-            Some(v) => v.call(()).unwrap_or_else(|e| {
+        if let Some(v) = on_load {
+            v.call(()).unwrap_or_else(|e| {
                 log.push_str(&format!("{:?}\n", e));
-            }),
-            None => return,
+            })
         }
+        self.flush_warnings(log);
     }
 
     pub fn call_draw(
         &mut self,
-        ctx: CtxRef,
+        target: plugin_ui::Target,
         lua_log: &Rc<RefCell<String>>,
         log: &mut String,
     ) -> Result<(), ()> {
-        if let None = self.lua {
+        if self.lua.is_none() || self.pending.is_some() {
             return Ok(());
         }
         let globals = self.lua.as_ref().unwrap().globals();
 
         let draw: Option<Function> = match globals.get("draw") {
-            Ok(v) => Some(v),
+            Ok(v) => v,
             Err(e) => {
                 log.push_str(&format!("{:?}\n", e));
                 None
             }
         };
 
-        let pui = plugin_ui::PluginUI::new(ctx, self.lua.as_ref().unwrap(), lua_log.clone());
-        match draw {
-            Some(v) => match v.call::<plugin_ui::PluginUI, ()>(pui) {
-                Ok(_) => Ok(()),
-                Err(e) => {
-                    log.push_str(&format!("{:?}", e));
-                    Err(())
-                }
-            },
-            None => return Ok(()),
+        let pui = plugin_ui::PluginUI::new(target, self.lua.as_ref().unwrap(), lua_log.clone());
+        let res = match draw {
+            Some(v) => v.call::<plugin_ui::PluginUI, ()>(pui),
+            None => Ok(()),
+        };
+        self.flush_warnings(log);
+        res.map_err(|e| {
+            let start = log.len();
+            log.push_str(&format!("{:?}", e));
+            self.note_error(log, start);
+        })
+    }
+
+    // Загрузка манифеста плагина
+    fn load_manifest(dir: &Path, log: &mut String) -> Option<Manifest> {
+        let path = dir.join("config.toml");
+
+        let data = match std::fs::read_to_string(&path) {
+            Ok(d) => d,
+            Err(e) => {
+                log.push_str(&format!("{:?}\n", e));
+                return None;
+            }
+        };
+
+        match toml::from_str(&data) {
+            Ok(m) => Some(m),
+            Err(e) => {
+                log.push_str(&format!("{}: {}\n", path.display(), e));
+                None
+            }
         }
     }
 
     // Загрузка lua плагина из файла
-    fn load_plugin_file(name: &String, log: &mut String) -> String {
-        let mut path = dirs::config_dir().unwrap();
-        path.push(format!("{}/{}/{}", "flexar/plugins", name, "plugin.lua"));
+    fn load_plugin_file(dir: &Path, log: &mut String) -> String {
+        let path = dir.join("plugin.lua");
 
         let mut file = match std::fs::File::open(path) {
             Ok(f) => f,
@@ -181,64 +707,122 @@ impl Plugin {
     }
 }
 
-pub fn unpack_plugins(apath: &std::path::Path, ppath: &std::path::Path, log: &mut String) {
-    let dir: &include_dir::Dir;
-    if ppath.to_string_lossy() == "" {
-        dir = &PLUGINS;
-    } else {
-        dir = match PLUGINS.get_dir(ppath) {
-            Some(d) => d,
-            None => {
-                log.push_str(&format!("Dir: {}, not found.\n", ppath.display()));
-                return;
+// Table holding the last part of dotted path, and that part
+fn parent<'a>(lua: &'static Lua, path: &'a str) -> mlua::Result<(mlua::Table<'static>, &'a str)> {
+    let mut table = lua.globals();
+    let mut parts = path.split('.');
+    let mut last = parts.next().unwrap_or_default();
+    for part in parts {
+        table = table.get(last)?;
+        last = part;
+    }
+    Ok((table, last))
+}
+
+// Defines old names of host functions as wrappers calling the replacement.
+// The first call of each adds a warning, so drawing doesn't flood the log.
+fn alias_deprecated(
+    lua: &'static Lua,
+    calls: &[(&str, &str, &str)],
+    plugin: &str,
+    warnings: Rc<RefCell<String>>,
+) -> mlua::Result<()> {
+    for (old, new, since) in calls {
+        let (table, name) = parent(lua, new)?;
+        let target: Function = table.get(name)?;
+        let message = format!(
+            "{}plugin {} calls `{}`, deprecated since API {}, use `{}` instead.\n",
+            WARNING, plugin, old, since, new
+        );
+        let warned = std::cell::Cell::new(false);
+        let warnings = warnings.clone();
+        let wrapper = lua.create_function(move |_, args: mlua::MultiValue| {
+            if !warned.replace(true) {
+                warnings.borrow_mut().push_str(&message);
             }
-        };
+            target.call::<_, mlua::MultiValue>(args)
+        })?;
+        let (table, name) = parent(lua, old)?;
+        table.set(name, wrapper)?;
     }
+    Ok(())
+}
 
-    for entry in dir.entries().iter() {
-        match entry {
-            File(file) => {
-                let mut path = apath.clone().to_path_buf();
-                path.push(file.path());
-                if !path.exists() {
-                    match std::fs::File::create(path) {
-                        Ok(mut f) => match write!(f, "{}", file.contents_utf8().unwrap()) {
-                            Ok(_) => (),
-                            Err(e) => log.push_str(&format!("{:?}\n", e)),
-                        },
-                        Err(e) => log.push_str(&format!("{:?}\n", e)),
-                    }
-                } else {
-                    match std::fs::write(path, file.contents_utf8().unwrap()) {
-                        Ok(_) => (),
-                        Err(e) => log.push_str(&format!("{:?}\n", e)),
-                    };
-                }
+// Metadata of installed plugin, None if its manifest can't be read
+pub fn read_info(name: &str) -> Option<Info> {
+    let mut log = String::new();
+    Plugin::load_manifest(&paths::get().plugin(name), &mut log).map(|m| m.info)
+}
+
+// Version from manifest of installed plugin
+pub fn installed_version(name: &str) -> Option<String> {
+    manifest_version(&paths::get().plugin(name))
+}
+
+// Manifest of a minimal plugin for tests, `extra` lines are appended to it
+#[cfg(test)]
+pub fn test_manifest(extra: &str) -> String {
+    format!(
+        "name = \"Test\"\nuse_photo = false\nuse_status_files = false\n\
+         use_subscribes_files = false\nuse_posts_files = false\nreg_methods = []\n{}",
+        extra
+    )
+}
+
+pub fn manifest_version(dir: &Path) -> Option<String> {
+    let mut log = String::new();
+    Plugin::load_manifest(dir, &mut log).map(|m| m.info.version)
+}
+
+// Проверка плагина без запуска: манифест разбирается, plugin.lua компилируется
+pub fn validate(dir: &Path) -> Vec<String> {
+    let read = |name: &str| {
+        std::fs::read_to_string(dir.join(name)).map_err(|e| format!("{}: {}", name, e))
+    };
+    validate_sources(read("config.toml"), read("plugin.lua"))
+}
+
+// То же для содержимого файлов, ещё не записанных на диск
+pub fn validate_sources(
+    manifest: Result<String, String>,
+    src: Result<String, String>,
+) -> Vec<String> {
+    let mut errors = Vec::new();
+    match manifest.and_then(|data| {
+        toml::from_str::<Manifest>(&data).map_err(|e| format!("config.toml: {}", e))
+    }) {
+        Ok(m) => {
+            if let Err(e) = m.capabilities() {
+                errors.push(e);
             }
-            Dir(d) => {
-                let mut path = apath.clone().to_path_buf();
-                path.push(d.path());
-                if !path.exists() {
-                    match std::fs::create_dir(path) {
-                        Ok(_) => (),
-                        Err(e) => log.push_str(&format!("{:?}\n", e)),
-                    }
-                }
-                unpack_plugins(&apath, d.path(), log);
+            errors.extend(m.info.validate());
+        }
+        Err(e) => errors.push(e),
+    }
+    match src {
+        Ok(src) => {
+            if let Err(e) = Lua::new()
+                .load(&src)
+                .set_name("plugin.lua")
+                .and_then(|c| c.into_function())
+            {
+                errors.push(format!("{}", e));
             }
         }
+        Err(e) => errors.push(e),
     }
+    errors
 }
 
 pub fn get_list(log: &mut String) -> Vec<String> {
-    let mut path = dirs::config_dir().unwrap();
-    path.push("flexar/plugins/".to_owned());
+    let path = paths::get().plugins();
     if !path.exists() {
         match std::fs::create_dir_all(&path) {
             Ok(_) => (),
             Err(e) => log.push_str(&format!("{:?}\n", e)),
         }
-        unpack_plugins(&path, PLUGINS.path(), log);
+        let plans = unpack::plan(&path, log);
+        unpack::apply(&path, &plans, log);
     }
 
     let files = match path.read_dir() {
@@ -249,19 +833,228 @@ pub fn get_list(log: &mut String) -> Vec<String> {
         }
     };
 
+    // only directories with a manifest are plugins, hidden ones are left
+    // from unfinished installs
     let mut file: Vec<String> = Vec::new();
     if let Some(entries) = files {
-        for e in entries {
-            if let Ok(entry) = e {
-                let mut path = entry.path();
-                if let Ok(ftype) = entry.file_type() {
-                    if ftype.is_dir() == true {
-                        path.set_extension("");
-                        file.push(String::from(path.file_name().unwrap().to_str().unwrap()));
-                    }
-                }
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with('.') && entry.path().join("config.toml").is_file() {
+                file.push(name);
             }
         }
     }
     file
 }
+
+// Installed plugins with metadata, by name. Unreadable manifests give defaults.
+pub fn list_with_info(log: &mut String) -> Vec<(String, Info)> {
+    let mut list: Vec<(String, Info)> = get_list(log)
+        .into_iter()
+        .map(|name| {
+            let info = read_info(&name).unwrap_or_default();
+            (name, info)
+        })
+        .collect();
+    list.sort_by(|a, b| a.0.cmp(&b.0));
+    list
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plugin_dir(name: &str, manifest: &str) -> PathBuf {
+        let dir = paths::get().root().join("plugin-tests").join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("config.toml"), test_manifest(manifest)).unwrap();
+        std::fs::write(dir.join("plugin.lua"), "function draw(ui) end\n").unwrap();
+        dir
+    }
+
+    fn load(name: &str, manifest: &str) -> String {
+        let dir = plugin_dir(name, manifest);
+        let mut log = String::new();
+        Plugin::new().load_dir(
+            name.to_owned(),
+            dir.clone(),
+            dir.join("data"),
+            None,
+            &mut log,
+        );
+        log
+    }
+
+    #[test]
+    fn warns_about_missing_api_version() {
+        let log = load("no-api", "");
+        assert!(log.starts_with(WARNING), "{}", log);
+        assert!(log.contains("doesn't declare api_version"), "{}", log);
+        assert!(load("with-api", "api_version = \"1.1\"\n").is_empty());
+    }
+
+    #[test]
+    fn refuses_unsupported_api() {
+        let log = load("old-api", "api_version = \">=0.1, <1\"\n");
+        assert!(
+            log.contains("supports plugin API >=0.1.0, <1.0.0"),
+            "{}",
+            log
+        );
+    }
+
+    #[test]
+    fn warns_once_about_deprecated_calls() {
+        let lua = Lua::new().into_static();
+        lua.load("util = { sum = function(a, b) return a + b end }")
+            .exec()
+            .unwrap();
+        let warnings = Rc::new(RefCell::new(String::new()));
+        alias_deprecated(
+            lua,
+            &[("add", "util.sum", "1.1"), ("util.plus", "util.sum", "1.1")],
+            "test",
+            warnings.clone(),
+        )
+        .unwrap();
+
+        let sum: i64 = lua.load("return add(1, 2) + add(3, 4)").eval().unwrap();
+        assert_eq!(sum, 10);
+        assert_eq!(
+            *warnings.borrow(),
+            format!(
+                "{}plugin test calls `add`, deprecated since API 1.1, use `util.sum` instead.\n",
+                WARNING
+            )
+        );
+        let sum: i64 = lua.load("return util.plus(1, 2)").eval().unwrap();
+        assert_eq!(sum, 3);
+        assert_eq!(warnings.borrow().lines().count(), 2);
+    }
+
+    #[test]
+    fn lists_installed_plugins_with_info() {
+        let dir = paths::get().plugin("listed");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("config.toml"),
+            std::fs::read_to_string(
+                plugin_dir("listed", "version = \"2.0\"\ndescription = \"Listed\"\n")
+                    .join("config.toml"),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        std::fs::create_dir_all(paths::get().plugins().join(".listed.old")).unwrap();
+
+        let list = list_with_info(&mut String::new());
+        let (_, info) = list.iter().find(|(n, _)| n == "listed").unwrap();
+        assert_eq!(info.title("listed"), "Test 2.0");
+        assert_eq!(info.description, "Listed");
+        assert!(!list.iter().any(|(n, _)| n.starts_with('.')));
+    }
+
+    #[test]
+    fn reports_load_status() {
+        let mut plugin = Plugin::new();
+        assert_eq!(plugin.load_status(), "not loaded");
+        let mut log = String::new();
+
+        let dir = plugin_dir(
+            "status-fs",
+            "api_version = \"1.1\"\npermissions = [\"fs\"]\n",
+        );
+        assert!(!plugin.load_dir(
+            "status-fs".to_owned(),
+            dir.clone(),
+            dir.join("data"),
+            None,
+            &mut log
+        ));
+        assert_eq!(plugin.load_status(), "waiting for permissions");
+
+        let dir = plugin_dir("status", "api_version = \"1.1\"\n");
+        assert!(plugin.load_dir(
+            "status".to_owned(),
+            dir.clone(),
+            dir.join("data"),
+            None,
+            &mut log
+        ));
+        assert_eq!(plugin.load_status(), "running");
+
+        let dir = plugin_dir("status-old", "api_version = \"0.1\"\n");
+        assert!(!plugin.load_dir(
+            "status-old".to_owned(),
+            dir.clone(),
+            dir.join("data"),
+            None,
+            &mut log
+        ));
+        assert_eq!(plugin.load_status(), "stopped");
+    }
+
+    #[test]
+    fn keeps_documentation_inside_plugin() {
+        let dir = plugin_dir("doc", "");
+        std::fs::write(dir.join("README.md"), "docs").unwrap();
+        let info = |homepage: &str| Info {
+            homepage: homepage.to_owned(),
+            ..Info::default()
+        };
+        assert_eq!(
+            info("README.md").doc_path(&dir).unwrap(),
+            dir.join("README.md").canonicalize().unwrap()
+        );
+        for bad in ["../doc/README.md", "/etc/passwd"] {
+            assert!(info(bad).doc_path(&dir).is_err(), "{}", bad);
+        }
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("/etc/passwd", dir.join("link.md")).unwrap();
+            assert!(info("link.md")
+                .doc_path(&dir)
+                .unwrap_err()
+                .contains("not inside"));
+        }
+    }
+
+    #[test]
+    fn calls_hooks_of_running_plugin() {
+        let dir = plugin_dir("hooks", "api_version = \"1.1\"\n");
+        std::fs::write(
+            dir.join("plugin.lua"),
+            "events = {}\n\
+             function on_config_loaded(name) table.insert(events, \"loaded \" .. name) end\n\
+             function on_config_saved(name) error(\"can't save \" .. name) end\n",
+        )
+        .unwrap();
+        let mut plugin = Plugin::new();
+        let mut log = String::new();
+        assert!(plugin.load_dir(
+            "hooks".to_owned(),
+            dir.clone(),
+            dir.join("data"),
+            None,
+            &mut log
+        ));
+        plugin.start(Vec::new(), &mut log, Rc::new(RefCell::new(String::new())));
+        assert!(log.is_empty(), "{}", log);
+
+        plugin.config_loaded("main", &mut log);
+        let events: Vec<String> = plugin.lua().unwrap().globals().get("events").unwrap();
+        assert_eq!(events, ["loaded main"]);
+        assert!(log.is_empty(), "{}", log);
+
+        // failing hook only reports to log
+        plugin.config_saved("main", &mut log);
+        assert!(log.contains("can't save main"), "{}", log);
+        assert!(plugin.last_error().unwrap().contains("can't save main"));
+
+        // plugin without on_shutdown is just stopped
+        log.clear();
+        plugin.shutdown(&mut log);
+        assert!(log.is_empty(), "{}", log);
+        assert_eq!(plugin.load_status(), "stopped");
+    }
+}
@@ -0,0 +1,249 @@
+use std::path::{Component, Path, PathBuf};
+
+use mlua::{Lua, Table};
+
+use crate::permissions::{Capability, Grants};
+
+// Prefix for paths inside the plugin's own folder (read-only)
+const PLUGIN_PREFIX: &str = "plugin:";
+
+// File access confined to the plugin folder and its data directory
+#[derive(Clone)]
+pub struct Sandbox {
+    plugin_dir: PathBuf,
+    data_dir: PathBuf,
+}
+
+impl Sandbox {
+    pub fn new(plugin_dir: PathBuf, data_dir: PathBuf) -> Self {
+        Self {
+            plugin_dir,
+            data_dir,
+        }
+    }
+
+    // Maps lua path to real path, rejecting everything outside of the sandbox
+    fn resolve(&self, path: &str, write: bool) -> Result<PathBuf, String> {
+        let (root, rel) = match path.strip_prefix(PLUGIN_PREFIX) {
+            Some(rel) if write => {
+                return Err(format!("'{}{}' is read-only", PLUGIN_PREFIX, rel));
+            }
+            Some(rel) => (&self.plugin_dir, rel),
+            None => {
+                if let Err(e) = std::fs::create_dir_all(&self.data_dir) {
+                    return Err(e.to_string());
+                }
+                (&self.data_dir, path)
+            }
+        };
+
+        let mut full = root.clone();
+        for c in Path::new(rel).components() {
+            match c {
+                Component::Normal(p) => full.push(p),
+                Component::CurDir => (),
+                Component::ParentDir => return Err(format!("'..' is not allowed: {}", path)),
+                Component::RootDir | Component::Prefix(_) => {
+                    return Err(format!("absolute paths are not allowed: {}", path))
+                }
+            }
+        }
+
+        // Symlinks may only point inside the sandbox root
+        let root = root.canonicalize().map_err(|e| e.to_string())?;
+        let mut existing = full.as_path();
+        while existing.symlink_metadata().is_err() {
+            existing = match existing.parent() {
+                Some(p) => p,
+                None => break,
+            };
+        }
+        let real = existing.canonicalize().map_err(|e| e.to_string())?;
+        if !real.starts_with(&root) {
+            return Err(format!("path escapes plugin directory: {}", path));
+        }
+
+        Ok(full)
+    }
+
+    fn read(&self, path: &str) -> Result<String, String> {
+        let path = self.resolve(path, false)?;
+        std::fs::read_to_string(path).map_err(|e| e.to_string())
+    }
+
+    fn write(&self, path: &str, data: &str) -> Result<(), String> {
+        let path = self.resolve(path, true)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        std::fs::write(path, data).map_err(|e| e.to_string())
+    }
+
+    fn list(&self, path: &str) -> Result<Vec<String>, String> {
+        let path = self.resolve(path, false)?;
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(path)
+            .map_err(|e| e.to_string())?
+            .flatten()
+        {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    fn exists(&self, path: &str) -> Result<bool, String> {
+        Ok(self.resolve(path, false)?.exists())
+    }
+
+    fn remove(&self, path: &str) -> Result<(), String> {
+        let path = self.resolve(path, true)?;
+        if path == self.data_dir {
+            return Err("can't remove data directory".to_owned());
+        }
+        if path.is_dir() {
+            std::fs::remove_dir_all(path).map_err(|e| e.to_string())
+        } else {
+            std::fs::remove_file(path).map_err(|e| e.to_string())
+        }
+    }
+}
+
+fn lua_err(api: &str, e: String) -> mlua::Error {
+    mlua::Error::RuntimeError(format!("{}: {}", api, e))
+}
+
+// Creates `fs` table for lua. Every call requires the `fs` capability.
+pub fn create_api<'lua>(
+    lua: &'lua Lua,
+    sandbox: Sandbox,
+    grants: Grants,
+) -> mlua::Result<Table<'lua>> {
+    let fs = lua.create_table()?;
+
+    let (sb, gr) = (sandbox.clone(), grants.clone());
+    fs.set(
+        "read",
+        lua.create_function(move |_, path: String| {
+            gr.check(Capability::Fs, "fs.read")?;
+            sb.read(&path).map_err(|e| lua_err("fs.read", e))
+        })?,
+    )?;
+
+    let (sb, gr) = (sandbox.clone(), grants.clone());
+    fs.set(
+        "write",
+        lua.create_function(move |_, (path, data): (String, String)| {
+            gr.check(Capability::Fs, "fs.write")?;
+            sb.write(&path, &data).map_err(|e| lua_err("fs.write", e))
+        })?,
+    )?;
+
+    let (sb, gr) = (sandbox.clone(), grants.clone());
+    fs.set(
+        "list",
+        lua.create_function(move |_, path: Option<String>| {
+            gr.check(Capability::Fs, "fs.list")?;
+            sb.list(&path.unwrap_or_default())
+                .map_err(|e| lua_err("fs.list", e))
+        })?,
+    )?;
+
+    let (sb, gr) = (sandbox.clone(), grants.clone());
+    fs.set(
+        "exists",
+        lua.create_function(move |_, path: String| {
+            gr.check(Capability::Fs, "fs.exists")?;
+            sb.exists(&path).map_err(|e| lua_err("fs.exists", e))
+        })?,
+    )?;
+
+    let (sb, gr) = (sandbox, grants);
+    fs.set(
+        "remove",
+        lua.create_function(move |_, path: String| {
+            gr.check(Capability::Fs, "fs.remove")?;
+            sb.remove(&path).map_err(|e| lua_err("fs.remove", e))
+        })?,
+    )?;
+
+    Ok(fs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sandbox(name: &str) -> (Sandbox, PathBuf) {
+        let root = crate::paths::test_dir(&format!("fs-{}", name));
+        let plugin_dir = root.join("plugin");
+        std::fs::create_dir_all(&plugin_dir).unwrap();
+        std::fs::write(plugin_dir.join("readme.txt"), "hi").unwrap();
+        let sb = Sandbox::new(plugin_dir, root.join("data"));
+        (sb, root)
+    }
+
+    #[test]
+    fn reads_and_writes_inside() {
+        let (sb, _) = sandbox("inside");
+        sb.write("notes/a.txt", "text").unwrap();
+        assert_eq!(sb.read("notes/a.txt").unwrap(), "text");
+        assert_eq!(sb.list("notes").unwrap(), vec!["a.txt"]);
+        assert_eq!(sb.read("plugin:readme.txt").unwrap(), "hi");
+        sb.remove("notes").unwrap();
+        assert!(!sb.exists("notes").unwrap());
+    }
+
+    #[test]
+    fn rejects_parent_and_absolute_paths() {
+        let (sb, _) = sandbox("escape");
+        assert!(sb.read("../plugin/readme.txt").unwrap_err().contains(".."));
+        assert!(sb.write("a/../../x", "x").unwrap_err().contains(".."));
+        assert!(sb.read("plugin:../data/x").unwrap_err().contains(".."));
+        assert!(sb.read("/etc/passwd").unwrap_err().contains("absolute"));
+        assert!(sb.write("/tmp/x", "x").unwrap_err().contains("absolute"));
+    }
+
+    #[test]
+    fn plugin_files_are_read_only() {
+        let (sb, root) = sandbox("readonly");
+        assert!(sb
+            .write("plugin:readme.txt", "x")
+            .unwrap_err()
+            .contains("read-only"));
+        assert!(sb
+            .remove("plugin:readme.txt")
+            .unwrap_err()
+            .contains("read-only"));
+        assert_eq!(
+            std::fs::read_to_string(root.join("plugin/readme.txt")).unwrap(),
+            "hi"
+        );
+    }
+
+    #[test]
+    fn keeps_data_root() {
+        let (sb, root) = sandbox("root");
+        sb.write("a.txt", "x").unwrap();
+        assert!(sb.remove("").is_err());
+        assert!(sb.remove(".").is_err());
+        assert!(root.join("data/a.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlink_outside() {
+        let (sb, root) = sandbox("symlink");
+        let outside = root.join("outside");
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(outside.join("secret.txt"), "secret").unwrap();
+        std::fs::create_dir_all(root.join("data")).unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("data/link")).unwrap();
+        assert!(sb.read("link/secret.txt").unwrap_err().contains("escapes"));
+        assert!(sb
+            .write("link/new.txt", "x")
+            .unwrap_err()
+            .contains("escapes"));
+        assert!(!outside.join("new.txt").exists());
+    }
+}
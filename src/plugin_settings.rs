@@ -0,0 +1,148 @@
+use std::{cell::RefCell, rc::Rc};
+
+use eframe::egui::{ComboBox, DragValue, TextEdit, Ui};
+use mlua::{MetaMethod, UserData, UserDataMethods};
+use serde_derive::Deserialize;
+use toml::{value::Table, Value};
+
+use crate::lua_codec;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SettingKind {
+    String,
+    Int,
+    Bool,
+    Enum,
+    Path,
+}
+
+// Setting declared by plugin in [[settings]] section of config.toml
+#[derive(Debug, Clone, Deserialize)]
+pub struct Setting {
+    pub key: String,
+    pub label: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: SettingKind,
+    pub default: Option<Value>,
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+    #[serde(default)]
+    pub options: Vec<String>,
+}
+
+impl Setting {
+    pub fn default_value(&self) -> Value {
+        if let Some(v) = &self.default {
+            if let Some(v) = self.coerce(v) {
+                return v;
+            }
+        }
+        match self.kind {
+            SettingKind::String | SettingKind::Path => Value::String(String::new()),
+            SettingKind::Int => Value::Integer(self.clamp(0)),
+            SettingKind::Bool => Value::Boolean(false),
+            SettingKind::Enum => Value::String(self.options.first().cloned().unwrap_or_default()),
+        }
+    }
+
+    // Returns value converted to the setting type or None if it doesn't fit
+    fn coerce(&self, value: &Value) -> Option<Value> {
+        match (&self.kind, value) {
+            (SettingKind::String | SettingKind::Path, Value::String(_)) => Some(value.clone()),
+            (SettingKind::Int, Value::Integer(i)) => Some(Value::Integer(self.clamp(*i))),
+            (SettingKind::Bool, Value::Boolean(_)) => Some(value.clone()),
+            (SettingKind::Enum, Value::String(s)) if self.options.contains(s) => {
+                Some(value.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn clamp(&self, i: i64) -> i64 {
+        i.max(self.min.unwrap_or(i64::MIN))
+            .min(self.max.unwrap_or(i64::MAX))
+    }
+}
+
+// Builds values for every declared setting, taking stored ones when they are valid
+pub fn resolve(decls: &[Setting], stored: Option<&Table>) -> Table {
+    let mut values = Table::new();
+    for s in decls {
+        let value = stored
+            .and_then(|t| t.get(&s.key))
+            .and_then(|v| s.coerce(v))
+            .unwrap_or_else(|| s.default_value());
+        values.insert(s.key.clone(), value);
+    }
+    values
+}
+
+// Draws form for declared settings. Returns true if any value was changed.
+pub fn show(ui: &mut Ui, decls: &[Setting], values: &mut Table) -> bool {
+    let mut changed = false;
+    for s in decls {
+        let value = values
+            .entry(s.key.clone())
+            .or_insert_with(|| s.default_value());
+        ui.horizontal(|ui| {
+            ui.label(format!("{}: ", s.label.as_ref().unwrap_or(&s.key)));
+            match (&s.kind, value) {
+                (SettingKind::String, Value::String(v)) => {
+                    changed |= ui.add(TextEdit::singleline(v)).changed();
+                }
+                (SettingKind::Path, Value::String(v)) => {
+                    changed |= ui.add(TextEdit::singleline(v)).changed();
+                    if !v.is_empty() && !std::path::Path::new(v).exists() {
+                        ui.label("(not found)");
+                    }
+                }
+                (SettingKind::Int, Value::Integer(v)) => {
+                    let min = s.min.unwrap_or(i64::MIN);
+                    let max = s.max.unwrap_or(i64::MAX);
+                    changed |= ui.add(DragValue::new(v).clamp_range(min..=max)).changed();
+                }
+                (SettingKind::Bool, Value::Boolean(v)) => {
+                    changed |= ui.checkbox(v, "").changed();
+                }
+                (SettingKind::Enum, Value::String(v)) => {
+                    ComboBox::from_id_source(format!("setting {}", s.key))
+                        .selected_text(v.clone())
+                        .show_ui(ui, |ui| {
+                            for o in &s.options {
+                                changed |= ui.selectable_value(v, o.clone(), o).changed();
+                            }
+                        });
+                }
+                _ => {
+                    ui.label("Invalid value");
+                }
+            }
+        });
+    }
+    changed
+}
+
+// Read-only view of plugin settings exposed to Lua as `settings`
+pub struct SettingsView(pub Rc<RefCell<Table>>);
+
+impl UserData for SettingsView {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_meta_method(MetaMethod::Index, |lua, view, key: String| {
+            match view.0.borrow().get(&key) {
+                Some(v) => lua_codec::toml_to_lua(lua, v),
+                None => Ok(mlua::Value::Nil),
+            }
+        });
+
+        methods.add_meta_method(
+            MetaMethod::NewIndex,
+            |_, _, (key, _): (String, mlua::Value)| {
+                Err::<(), _>(mlua::Error::RuntimeError(format!(
+                    "settings are read-only, can't assign '{}'",
+                    key
+                )))
+            },
+        );
+    }
+}
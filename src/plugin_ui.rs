@@ -1,70 +1,215 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 use eframe::egui::{Align, CentralPanel, CtxRef, ScrollArea, Ui};
 use mlua::{Function, Lua, RegistryKey, Table, UserData, UserDataMethods};
 
+// Widget tree node produced by draw() in recording mode
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub kind: &'static str,
+    pub id: String,
+    pub text: String,
+    pub value: Option<String>,
+    pub children: Vec<Node>,
+}
+
+// Widgets drawn in recording mode, with handlers to simulate user actions
+#[derive(Default)]
+pub struct Recording {
+    pub nodes: Vec<Node>,
+    pub handlers: HashMap<String, (&'static str, Rc<RegistryKey>)>,
+    pub errors: Vec<String>,
+}
+
+// Where widgets go: onto the screen or into the recording
+#[derive(Clone)]
+pub enum Target {
+    Screen(CtxRef),
+    Record(Rc<RefCell<Recording>>),
+}
+
+#[derive(Clone)]
+pub struct Widget {
+    kind: &'static str,
+    draw: fn(&PluginUI, &mut Ui, Table),
+    record: fn(&PluginUI, Table, Node) -> Node,
+    key: Rc<RegistryKey>,
+}
+
 #[derive(Clone)]
 pub struct PluginUI {
-    pub ctx: CtxRef,
-    pub widgets: Vec<Rc<RefCell<(Box<dyn FnMut(&PluginUI, &mut Ui, Table)>, RegistryKey)>>>,
+    pub target: Target,
+    pub widgets: Vec<Widget>,
     pub lua: &'static Lua,
     pub lua_log: Rc<RefCell<String>>,
 }
 
 impl PluginUI {
-    pub fn new(ctx: CtxRef, lua: &'static Lua, lua_log: Rc<RefCell<String>>) -> Self {
+    pub fn new(target: Target, lua: &'static Lua, lua_log: Rc<RefCell<String>>) -> Self {
         Self {
-            ctx,
+            target,
             widgets: Vec::new(),
             lua,
             lua_log,
         }
     }
 
+    fn report(&self, e: mlua::Error) {
+        match &self.target {
+            Target::Screen(_) => self.lua_log.borrow_mut().push_str(&format!("{:?}", e)),
+            Target::Record(rec) => rec.borrow_mut().errors.push(format!("{}", e)),
+        }
+    }
+
+    fn text(table: &Table, default: &str) -> String {
+        table
+            .get::<&str, String>("text")
+            .unwrap_or_else(|_| default.to_owned())
+    }
+
     fn label(_pui: &PluginUI, ui: &mut Ui, table: Table) {
-        ui.label(
-            table
-                .get::<&str, String>("text")
-                .unwrap_or("label".to_owned()),
-        );
+        ui.label(PluginUI::text(&table, "label"));
+    }
+
+    fn record_label(_pui: &PluginUI, table: Table, mut node: Node) -> Node {
+        node.text = PluginUI::text(&table, "label");
+        node
+    }
+
+    // Builds children of collapsing header by calling its callback
+    fn collapsing_body(pui: &PluginUI, table: &Table) -> Option<PluginUI> {
+        let func = table.get::<&str, Option<Function>>("cb").unwrap_or(None)?;
+        let pui_arg = PluginUI::new(pui.target.clone(), pui.lua, pui.lua_log.clone());
+        Some(
+            func.call::<PluginUI, PluginUI>(pui_arg)
+                .unwrap_or_else(|e| {
+                    pui.report(e);
+                    PluginUI::new(pui.target.clone(), pui.lua, pui.lua_log.clone())
+                }),
+        )
     }
 
     fn collapsing(pui: &PluginUI, ui: &mut Ui, table: Table) {
-        let label = table
-            .get::<&str, String>("text")
-            .unwrap_or("Collapsing".to_owned());
-        let func = table.get::<&str, Option<Function>>("cb").unwrap_or(None);
+        let label = PluginUI::text(&table, "Collapsing");
         ui.collapsing(label, |ui| {
-            if let Some(f) = func {
-                let mut pui_arg = PluginUI::new(pui.ctx.clone(), pui.lua, pui.lua_log.clone());
-                pui_arg = f.call::<PluginUI, PluginUI>(pui_arg).unwrap_or_else(|e| {
-                    (*pui).lua_log.borrow_mut().push_str(&format!("{:?}", e));
-                    PluginUI::new(pui.ctx.clone(), pui.lua, pui.lua_log.clone())
-                });
+            if let Some(mut pui_arg) = PluginUI::collapsing_body(pui, &table) {
                 pui_arg.draw_ui(ui);
             }
         });
     }
 
+    fn record_collapsing(pui: &PluginUI, table: Table, mut node: Node) -> Node {
+        node.text = PluginUI::text(&table, "Collapsing");
+        if let Some(pui_arg) = PluginUI::collapsing_body(pui, &table) {
+            node.children = pui_arg.record_ui(&node.id);
+        }
+        node
+    }
+
     fn button(pui: &PluginUI, ui: &mut Ui, table: Table) {
-        let label = table
-            .get::<&str, String>("text")
-            .unwrap_or("Button".to_owned());
+        let label = PluginUI::text(&table, "Button");
         let func = table.get::<&str, Option<Function>>("cb").unwrap_or(None);
         if ui.button(label).clicked() {
             if let Some(f) = func {
-                f.call(()).unwrap_or_else(|e| {
-                    (*pui).lua_log.borrow_mut().push_str(&format!("{:?}", e));
-                });
+                f.call(()).unwrap_or_else(|e| pui.report(e));
             }
         };
     }
 
+    fn record_button(_pui: &PluginUI, table: Table, mut node: Node) -> Node {
+        node.text = PluginUI::text(&table, "Button");
+        node
+    }
+
+    fn text_edit(pui: &PluginUI, ui: &mut Ui, table: Table) {
+        let mut text = PluginUI::text(&table, "");
+        let func = table.get::<&str, Option<Function>>("cb").unwrap_or(None);
+        if ui.text_edit_singleline(&mut text).changed() {
+            if let Some(f) = func {
+                f.call(text).unwrap_or_else(|e| pui.report(e));
+            }
+        }
+    }
+
+    fn record_text_edit(_pui: &PluginUI, table: Table, mut node: Node) -> Node {
+        node.value = Some(PluginUI::text(&table, ""));
+        node
+    }
+
+    fn checkbox(pui: &PluginUI, ui: &mut Ui, table: Table) {
+        let label = PluginUI::text(&table, "");
+        let mut checked = table.get::<&str, bool>("checked").unwrap_or(false);
+        let func = table.get::<&str, Option<Function>>("cb").unwrap_or(None);
+        if ui.checkbox(&mut checked, label).changed() {
+            if let Some(f) = func {
+                f.call(checked).unwrap_or_else(|e| pui.report(e));
+            }
+        }
+    }
+
+    fn record_checkbox(_pui: &PluginUI, table: Table, mut node: Node) -> Node {
+        node.text = PluginUI::text(&table, "");
+        node.value = Some(
+            table
+                .get::<&str, bool>("checked")
+                .unwrap_or(false)
+                .to_string(),
+        );
+        node
+    }
+
     pub fn draw_ui(&mut self, ui: &mut Ui) {
-        for i in self.widgets.clone() {
-            let reg_val = self.lua.registry_value::<Table>(&i.borrow().1).unwrap();
-            (*i).borrow_mut().0(self, ui, reg_val);
+        for w in self.widgets.clone() {
+            let reg_val = self.lua.registry_value::<Table>(&w.key).unwrap();
+            (w.draw)(self, ui, reg_val);
+        }
+    }
+
+    // Records widgets as nodes. Ids are taken from `id` field or generated from position.
+    pub fn record_ui(&self, parent: &str) -> Vec<Node> {
+        let rec = match &self.target {
+            Target::Record(rec) => rec.clone(),
+            Target::Screen(_) => return Vec::new(),
+        };
+        let mut nodes = Vec::new();
+        for (i, w) in self.widgets.iter().enumerate() {
+            let table = self.lua.registry_value::<Table>(&w.key).unwrap();
+            let id = match table.get::<&str, Option<String>>("id").unwrap_or(None) {
+                Some(id) => id,
+                None if parent.is_empty() => format!("{}{}", w.kind, i),
+                None => format!("{}/{}{}", parent, w.kind, i),
+            };
+            rec.borrow_mut()
+                .handlers
+                .insert(id.clone(), (w.kind, w.key.clone()));
+            let node = Node {
+                kind: w.kind,
+                id,
+                text: String::new(),
+                value: None,
+                children: Vec::new(),
+            };
+            nodes.push((w.record)(self, table, node));
         }
+        nodes
+    }
+
+    fn add(
+        &mut self,
+        lua: &Lua,
+        table: Table,
+        kind: &'static str,
+        draw: fn(&PluginUI, &mut Ui, Table),
+        record: fn(&PluginUI, Table, Node) -> Node,
+    ) -> mlua::Result<()> {
+        let key = lua.create_registry_value(table)?;
+        self.widgets.push(Widget {
+            kind,
+            draw,
+            record,
+            key: Rc::new(key),
+        });
+        Ok(())
     }
 }
 
@@ -72,42 +217,73 @@ impl UserData for PluginUI {
     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
         // drawing ui
         methods.add_method_mut("show", |_, ctx, _: ()| {
-            CentralPanel::default().show(&ctx.ctx.clone(), |ui| {
-                ScrollArea::vertical().show(ui, |ui| {
-                    ui.with_layout(
-                        eframe::egui::Layout::top_down_justified(Align::Center),
-                        |ui| {
-                            ctx.draw_ui(ui);
-                        },
-                    );
-                });
-            });
+            match ctx.target.clone() {
+                Target::Screen(screen) => {
+                    CentralPanel::default().show(&screen, |ui| {
+                        ScrollArea::vertical().show(ui, |ui| {
+                            ui.with_layout(
+                                eframe::egui::Layout::top_down_justified(Align::Center),
+                                |ui| {
+                                    ctx.draw_ui(ui);
+                                },
+                            );
+                        });
+                    });
+                }
+                Target::Record(rec) => {
+                    let nodes = ctx.record_ui("");
+                    rec.borrow_mut().nodes.extend(nodes);
+                }
+            }
             Ok(())
         });
 
         // adding label for draw
         methods.add_method_mut("label", |lua, ctx, table: Table| {
-            let reg_key = lua.create_registry_value(table).unwrap();
-            ctx.widgets
-                .push(Rc::new(RefCell::new((Box::new(PluginUI::label), reg_key))));
-            Ok(())
+            ctx.add(lua, table, "label", PluginUI::label, PluginUI::record_label)
         });
 
         // adding CollapsingHeader to draw
         methods.add_method_mut("collapsing", |lua, ctx, table: Table| {
-            let reg_key = lua.create_registry_value(table).unwrap();
-            ctx.widgets.push(Rc::new(RefCell::new((
-                Box::new(PluginUI::collapsing),
-                reg_key,
-            ))));
-            Ok(())
+            ctx.add(
+                lua,
+                table,
+                "collapsing",
+                PluginUI::collapsing,
+                PluginUI::record_collapsing,
+            )
         });
 
         methods.add_method_mut("button", |lua, ctx, table: Table| {
-            let reg_key = lua.create_registry_value(table).unwrap();
-            ctx.widgets
-                .push(Rc::new(RefCell::new((Box::new(PluginUI::button), reg_key))));
-            Ok(())
+            ctx.add(
+                lua,
+                table,
+                "button",
+                PluginUI::button,
+                PluginUI::record_button,
+            )
+        });
+
+        // single line input, cb receives new text
+        methods.add_method_mut("text_edit", |lua, ctx, table: Table| {
+            ctx.add(
+                lua,
+                table,
+                "text_edit",
+                PluginUI::text_edit,
+                PluginUI::record_text_edit,
+            )
+        });
+
+        // cb receives new state
+        methods.add_method_mut("checkbox", |lua, ctx, table: Table| {
+            ctx.add(
+                lua,
+                table,
+                "checkbox",
+                PluginUI::checkbox,
+                PluginUI::record_checkbox,
+            )
         });
     }
 }
@@ -0,0 +1,273 @@
+use std::path::{Path, PathBuf};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{archive, backup, config, install, integrity, paths, plugin, session};
+
+pub const EXTENSIONS: &[&str] = &["zip"];
+
+// Entries of exported profile
+const PROFILE_ENTRY: &str = "profile.toml";
+const CONFIG_ENTRY: &str = "config.toml";
+const PLUGIN_DIR: &str = "plugin";
+
+// Named set of saved config, plugin with its version and settings, and window layout
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub config: String,
+    pub plugin: Option<String>,
+    pub plugin_version: String,
+    pub settings: toml::value::Table,
+    pub layout: session::Layout,
+}
+
+impl Profile {
+    pub fn list(log: &mut String) -> Vec<String> {
+        let dir = paths::get().profiles();
+        let rd = match std::fs::read_dir(&dir) {
+            Ok(rd) => rd,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+            Err(e) => {
+                log.push_str(&format!("{}: {}\n", dir.display(), e));
+                return Vec::new();
+            }
+        };
+        let mut names: Vec<String> = rd
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|e| e == "toml"))
+            .filter_map(|p| p.file_stem().map(|s| s.to_string_lossy().into_owned()))
+            .collect();
+        names.sort();
+        names
+    }
+
+    pub fn exists(name: &str) -> bool {
+        paths::get().profile_file(name).exists()
+    }
+
+    pub fn load(name: &str) -> Result<Profile, String> {
+        let path = paths::get().profile_file(name);
+        let data =
+            std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Profile::parse(&data).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn parse(data: &str) -> Result<Profile, String> {
+        let p: Profile = toml::from_str(data).map_err(|e| e.to_string())?;
+        config::Config::check_name(&p.config).map_err(|e| format!("config: {}", e))?;
+        if let Some(name) = &p.plugin {
+            config::Config::check_name(name).map_err(|e| format!("plugin: {}", e))?;
+        }
+        Ok(p)
+    }
+
+    pub fn save(&self, name: &str) -> Result<(), String> {
+        config::Config::check_name(name)?;
+        let data = toml::to_vec(self).map_err(|e| e.to_string())?;
+        std::fs::create_dir_all(paths::get().profiles()).map_err(|e| e.to_string())?;
+        backup::write_atomic(&paths::get().profile_file(name), &data).map_err(|e| e.to_string())
+    }
+
+    pub fn delete(name: &str) -> Result<(), String> {
+        std::fs::remove_file(paths::get().profile_file(name)).map_err(|e| e.to_string())
+    }
+
+    // Puts plugin of profile and its settings into config
+    pub fn apply(&self, conf: &mut config::Config) {
+        conf.set_website(self.plugin.clone());
+        if let Some(name) = &self.plugin {
+            conf.set_plugin_settings(name, self.settings.clone());
+        }
+    }
+
+    // Writes archive with profile, its config and files of its plugin
+    pub fn export(name: &str, path: &Path) -> Result<(), String> {
+        let p = Profile::load(name)?;
+        let conf_path = paths::get().config_file(&p.config);
+        let conf =
+            std::fs::read(&conf_path).map_err(|e| format!("{}: {}", conf_path.display(), e))?;
+        let mut entries = vec![
+            (
+                PathBuf::from(PROFILE_ENTRY),
+                std::fs::read(paths::get().profile_file(name)).map_err(|e| e.to_string())?,
+            ),
+            (PathBuf::from(CONFIG_ENTRY), conf),
+        ];
+        if let Some(plugin) = &p.plugin {
+            let dir = paths::get().plugin(plugin);
+            if dir.is_dir() {
+                entries.extend(archive::collect(&dir, Path::new(PLUGIN_DIR))?);
+            }
+        }
+        archive::write(path, &entries)
+    }
+
+    // Adds profile from archive, named after the archive file. Config that
+    // clashes with an existing one is imported under a new name. Plugin is
+    // installed only if it isn't installed yet.
+    pub fn import(path: &Path, log: &mut String) -> Result<String, String> {
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        config::Config::check_name(&name)?;
+        if Profile::exists(&name) {
+            return Err(format!("profile {} already exists", name));
+        }
+        let entries = archive::read(path)?;
+        let entry = |n: &str| {
+            entries
+                .iter()
+                .find(|(p, _)| p == Path::new(n))
+                .map(|(_, d)| d.clone())
+                .ok_or(format!("archive has no {}", n))
+        };
+        let data = String::from_utf8(entry(PROFILE_ENTRY)?).map_err(|e| e.to_string())?;
+        let mut p = Profile::parse(&data).map_err(|e| format!("{}: {}", PROFILE_ENTRY, e))?;
+        let conf = entry(CONFIG_ENTRY)?;
+
+        if let Some(plugin) = &p.plugin {
+            let dir = paths::get().plugin(plugin);
+            if dir.exists() {
+                match plugin::installed_version(plugin) {
+                    Some(v) if v != p.plugin_version => log.push_str(&format!(
+                        "Profile {} uses {} {}, installed version {} is kept.\n",
+                        name, plugin, p.plugin_version, v
+                    )),
+                    _ => (),
+                }
+            } else {
+                Profile::install_plugin(plugin, &entries, log)?;
+            }
+        }
+
+        let existing = std::fs::read(paths::get().config_file(&p.config)).ok();
+        match existing {
+            Some(data) if data == conf => (),
+            Some(_) => {
+                let base = format!("{}-{}", p.config, name);
+                let new = std::iter::once(base.clone())
+                    .chain((2..).map(|n| format!("{}-{}", base, n)))
+                    .find(|n| !config::Config::exists(n))
+                    .unwrap_or(base);
+                log.push_str(&format!(
+                    "Config {} already exists, config of profile {} was imported as {}.\n",
+                    p.config, name, new
+                ));
+                p.config = new;
+                config::Config::replace(&p.config, &conf)?;
+            }
+            None => config::Config::replace(&p.config, &conf)?,
+        }
+        p.save(&name)?;
+        Ok(name)
+    }
+
+    // Installs plugin files of archive the same way as a plugin archive
+    fn install_plugin(
+        name: &str,
+        entries: &archive::Entries,
+        log: &mut String,
+    ) -> Result<(), String> {
+        let files: archive::Entries = entries
+            .iter()
+            .filter_map(|(p, d)| {
+                p.strip_prefix(PLUGIN_DIR)
+                    .ok()
+                    .map(|p| (p.to_owned(), d.clone()))
+            })
+            .collect();
+        if files.is_empty() {
+            log.push_str(&format!(
+                "Plugin {} isn't installed and profile doesn't include it.\n",
+                name
+            ));
+            return Ok(());
+        }
+        let pkg = install::package(name.to_owned(), files)
+            .map_err(|e| format!("plugin {}: {}", name, e))?;
+        install::install_package(pkg, false, log)?;
+        let status = integrity::check(&paths::get().plugin(name), &integrity::trusted_keys(log));
+        log.push_str(&format!(
+            "Plugin {} from profile is {}.\n",
+            name,
+            status.label()
+        ));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = "function on_load() end\nfunction draw(ui) end\n";
+
+    // Writes profile archive named `name`.zip using config `config` and plugin `plugin`
+    fn archive(name: &str, config: &str, reg_num: u32, plugin: &str, script: &str) -> PathBuf {
+        let dir = paths::get().root().join("archives");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.zip", name));
+        let profile = format!(
+            "config = \"{}\"\nplugin = \"{}\"\nplugin_version = \"1.0.0\"\n",
+            config, plugin
+        );
+        let entries = vec![
+            (PathBuf::from(PROFILE_ENTRY), profile.into_bytes()),
+            (
+                PathBuf::from(CONFIG_ENTRY),
+                format!("reg_num = {}\n", reg_num).into_bytes(),
+            ),
+            (
+                Path::new(PLUGIN_DIR).join("config.toml"),
+                plugin::test_manifest("version = \"1.0.0\"\n").into_bytes(),
+            ),
+            (
+                Path::new(PLUGIN_DIR).join("plugin.lua"),
+                script.as_bytes().to_vec(),
+            ),
+        ];
+        archive::write(&path, &entries).unwrap();
+        path
+    }
+
+    #[test]
+    fn imports_clashing_config_under_new_name() {
+        let paths = paths::get();
+        let mut log = String::new();
+        let first = archive("clash-a", "clash", 5, "clash-plugin", SCRIPT);
+        assert_eq!(Profile::import(&first, &mut log).unwrap(), "clash-a");
+        assert!(paths.plugin("clash-plugin").join("plugin.lua").is_file());
+        assert!(log.contains("from profile is unsigned"), "{}", log);
+
+        // same config again is reused as it is
+        let same = archive("clash-b", "clash", 5, "clash-plugin", SCRIPT);
+        Profile::import(&same, &mut log).unwrap();
+        assert_eq!(Profile::load("clash-b").unwrap().config, "clash");
+
+        let other = archive("clash-c", "clash", 7, "clash-plugin", SCRIPT);
+        Profile::import(&other, &mut log).unwrap();
+        assert_eq!(Profile::load("clash-c").unwrap().config, "clash-clash-c");
+        assert_eq!(
+            std::fs::read_to_string(paths.config_file("clash")).unwrap(),
+            "reg_num = 5\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(paths.config_file("clash-clash-c")).unwrap(),
+            "reg_num = 7\n"
+        );
+    }
+
+    #[test]
+    fn refuses_invalid_plugin() {
+        let mut log = String::new();
+        let path = archive("broken", "broken", 1, "broken-plugin", "function (");
+        assert!(Profile::import(&path, &mut log).is_err());
+        let paths = paths::get();
+        assert!(!paths.plugin("broken-plugin").exists());
+        assert!(!paths.plugins().join(".broken-plugin.install").exists());
+        assert!(!Profile::exists("broken"));
+    }
+}
@@ -0,0 +1,335 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde_derive::Deserialize;
+
+use crate::{config, install, paths, unpack};
+
+// Index file name used when repository location is a directory
+const INDEX: &str = "index.toml";
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// Plugin offered by repository. Archive path is relative to the index.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    pub archive: String,
+    pub sha256: String,
+}
+
+#[derive(Deserialize)]
+struct Index {
+    #[serde(default)]
+    plugins: Vec<Entry>,
+}
+
+// Where index is read from: a local file or a plain HTTP URL
+#[derive(Debug, PartialEq)]
+pub enum Source {
+    Local(PathBuf),
+    Http {
+        host: String,
+        port: u16,
+        path: String,
+    },
+}
+
+impl Source {
+    pub fn parse(location: &str) -> Result<Source, String> {
+        let location = location.trim();
+        if location.is_empty() {
+            return Err("repository is not set".to_owned());
+        }
+        if location.starts_with("https://") {
+            return Err("only http:// repositories are supported".to_owned());
+        }
+        if let Some(rest) = location.strip_prefix("http://") {
+            let (authority, path) = match rest.find('/') {
+                Some(i) => rest.split_at(i),
+                None => (rest, "/"),
+            };
+            let (host, port) = match authority.rsplit_once(':') {
+                Some((h, p)) => (
+                    h,
+                    p.parse()
+                        .map_err(|_| format!("invalid port in {}", location))?,
+                ),
+                None => (authority, 80),
+            };
+            if host.is_empty() {
+                return Err(format!("no host in {}", location));
+            }
+            let mut path = path.to_owned();
+            if path.ends_with('/') {
+                path.push_str(INDEX);
+            }
+            return Ok(Source::Http {
+                host: host.to_owned(),
+                port,
+                path,
+            });
+        }
+        let path = PathBuf::from(location);
+        if path.is_dir() {
+            Ok(Source::Local(path.join(INDEX)))
+        } else {
+            Ok(Source::Local(path))
+        }
+    }
+
+    // Reads file at location relative to the index
+    fn fetch(&self, rel: &str) -> Result<Vec<u8>, String> {
+        match self {
+            Source::Local(index) => {
+                let path = match index.parent() {
+                    Some(dir) if !rel.is_empty() => dir.join(rel),
+                    _ => index.clone(),
+                };
+                std::fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))
+            }
+            Source::Http { host, port, path } => {
+                if rel.starts_with("http://") {
+                    return Source::parse(rel)?.fetch("");
+                }
+                let target = if rel.is_empty() {
+                    path.clone()
+                } else if rel.starts_with('/') {
+                    rel.to_owned()
+                } else {
+                    let dir = &path[..path.rfind('/').map_or(0, |i| i + 1)];
+                    format!("{}{}", dir, rel)
+                };
+                http_get(host, *port, &target)
+            }
+        }
+    }
+
+    pub fn index(&self) -> Result<Vec<Entry>, String> {
+        let data = self.fetch("")?;
+        let text = String::from_utf8(data).map_err(|e| e.to_string())?;
+        let index: Index = toml::from_str(&text).map_err(|e| format!("{}: {}", INDEX, e))?;
+        Ok(index.plugins)
+    }
+
+    // Fetches archive of entry into dir, checksum must match the index
+    pub fn download(&self, entry: &Entry, dir: &Path) -> Result<PathBuf, String> {
+        config::Config::check_name(&entry.name).map_err(|e| format!("plugin {}", e))?;
+        let data = self.fetch(&entry.archive)?;
+        let sum = unpack::checksum(&data);
+        if !sum.eq_ignore_ascii_case(entry.sha256.trim()) {
+            return Err(format!(
+                "checksum of {} doesn't match the index",
+                entry.archive
+            ));
+        }
+        std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        // installer names plugins from archive root after the file
+        let path = dir.join(format!("{}.zip", entry.name));
+        std::fs::write(&path, data).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(path)
+    }
+}
+
+// Minimal HTTP/1.0 GET, so the body is never chunked
+fn http_get(host: &str, port: u16, path: &str) -> Result<Vec<u8>, String> {
+    let url = format!("http://{}:{}{}", host, port, path);
+    let err = |e: std::io::Error| format!("{}: {}", url, e);
+    let addr = (host, port)
+        .to_socket_addrs()
+        .map_err(err)?
+        .next()
+        .ok_or(format!("{}: host not found", url))?;
+    let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT).map_err(err)?;
+    stream.set_read_timeout(Some(TIMEOUT)).map_err(err)?;
+    stream.set_write_timeout(Some(TIMEOUT)).map_err(err)?;
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, host
+    );
+    stream.write_all(request.as_bytes()).map_err(err)?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).map_err(err)?;
+
+    let end = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or(format!("{}: malformed response", url))?;
+    let head = String::from_utf8_lossy(&response[..end]).into_owned();
+    let mut body = response.split_off(end + 4);
+    let mut lines = head.lines();
+    let status = lines.next().unwrap_or_default();
+    let code = status.split_whitespace().nth(1).unwrap_or_default();
+    if code != "200" {
+        return Err(format!("{}: {}", url, status));
+    }
+    for line in lines {
+        if let Some((k, v)) = line.split_once(':') {
+            if k.trim().eq_ignore_ascii_case("content-length") {
+                if let Ok(len) = v.trim().parse::<usize>() {
+                    body.truncate(len);
+                }
+            }
+        }
+    }
+    Ok(body)
+}
+
+// Downloads plugin and installs it, replacing installed version if any
+pub fn install(source: &Source, entry: &Entry, log: &mut String) -> Result<String, String> {
+    let path = source.download(entry, &paths::get().downloads())?;
+    let res = install::inspect(&path).and_then(|pkg| {
+        if pkg.name != entry.name {
+            return Err(format!(
+                "archive holds plugin {} instead of {}",
+                pkg.name, entry.name
+            ));
+        }
+        let update = paths::get().plugin(&pkg.name).exists();
+        install::install_package(pkg, update, log)
+    });
+    let _ = std::fs::remove_file(&path);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::HashMap, io::BufRead, net::TcpListener};
+
+    // Writes archive of a small plugin, returns its bytes
+    fn plugin_zip(dir: &Path) -> Vec<u8> {
+        let path = dir.join("tp.zip");
+        crate::archive::write(
+            &path,
+            &[
+                (
+                    PathBuf::from("tp/config.toml"),
+                    crate::plugin::test_manifest("version = \"1.2.0\"\n").into_bytes(),
+                ),
+                (PathBuf::from("tp/plugin.lua"), b"x = 1".to_vec()),
+            ],
+        )
+        .unwrap();
+        std::fs::read(path).unwrap()
+    }
+
+    fn index(sum: &str) -> String {
+        format!(
+            "[[plugins]]\nname = \"tp\"\nversion = \"1.2.0\"\n\
+             description = \"Test plugin\"\narchive = \"tp.zip\"\nsha256 = \"{}\"\n",
+            sum
+        )
+    }
+
+    // Serves files from memory, one connection per request
+    fn serve(files: HashMap<String, Vec<u8>>, requests: usize) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    header.clear();
+                }
+                let path = line.split_whitespace().nth(1).unwrap_or_default();
+                let response = match files.get(path) {
+                    Some(body) => {
+                        let mut r =
+                            format!("HTTP/1.0 200 OK\r\nContent-Length: {}\r\n\r\n", body.len())
+                                .into_bytes();
+                        r.extend(body);
+                        r
+                    }
+                    None => b"HTTP/1.0 404 Not Found\r\n\r\n".to_vec(),
+                };
+                stream.write_all(&response).unwrap();
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn parses_locations() {
+        assert_eq!(
+            Source::parse("http://files.local:8080/plugins/").unwrap(),
+            Source::Http {
+                host: "files.local".to_owned(),
+                port: 8080,
+                path: "/plugins/index.toml".to_owned(),
+            }
+        );
+        assert_eq!(
+            Source::parse("http://files.local").unwrap(),
+            Source::Http {
+                host: "files.local".to_owned(),
+                port: 80,
+                path: "/index.toml".to_owned(),
+            }
+        );
+        assert!(Source::parse("https://files.local/").is_err());
+        assert!(Source::parse("").is_err());
+    }
+
+    #[test]
+    fn reads_local_directory() {
+        let dir = paths::test_dir("repo-local");
+        let zip = plugin_zip(&dir);
+        std::fs::write(dir.join(INDEX), index(&unpack::checksum(&zip))).unwrap();
+
+        let source = Source::parse(dir.to_str().unwrap()).unwrap();
+        let entries = source.index().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].description, "Test plugin");
+        let path = source.download(&entries[0], &dir.join("dl")).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), zip);
+        assert_eq!(install::inspect(&path).unwrap().version, "1.2.0");
+    }
+
+    #[test]
+    fn refuses_checksum_mismatch() {
+        let dir = paths::test_dir("repo-mismatch");
+        plugin_zip(&dir);
+        std::fs::write(dir.join(INDEX), index(&unpack::checksum(b"other"))).unwrap();
+
+        let source = Source::parse(dir.to_str().unwrap()).unwrap();
+        let entries = source.index().unwrap();
+        assert!(source
+            .download(&entries[0], &dir.join("dl"))
+            .unwrap_err()
+            .contains("checksum"));
+        assert!(!dir.join("dl").join("tp.zip").exists());
+    }
+
+    #[test]
+    fn reads_http_server() {
+        let dir = paths::test_dir("repo-http");
+        let zip = plugin_zip(&dir);
+        let mut files = HashMap::new();
+        files.insert(
+            "/repo/index.toml".to_owned(),
+            index(&unpack::checksum(&zip)).into_bytes(),
+        );
+        files.insert("/repo/tp.zip".to_owned(), zip.clone());
+        let port = serve(files, 3);
+
+        let source = Source::parse(&format!("http://127.0.0.1:{}/repo/", port)).unwrap();
+        let entries = source.index().unwrap();
+        assert_eq!(entries[0].name, "tp");
+        let path = source.download(&entries[0], &dir.join("dl")).unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), zip);
+
+        let missing = Source::parse(&format!("http://127.0.0.1:{}/none/", port)).unwrap();
+        assert!(missing.index().unwrap_err().contains("404"));
+    }
+}
@@ -0,0 +1,428 @@
+use serde::de::{
+    self, value::Error, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess,
+    SeqAccess, VariantAccess, Visitor,
+};
+
+// Shape of a type as seen by serde, used to build editors
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    Bool,
+    Int { min: i64, max: i64 },
+    Float,
+    Str,
+    // Variants with shapes of their values, None for unit variants
+    Enum(Vec<(&'static str, Option<Shape>)>),
+    Option(Box<Shape>),
+    Seq(Box<Shape>),
+    Struct(Vec<(&'static str, Shape)>),
+    Map,
+    Unknown,
+}
+
+// Finds shape of T by letting it deserialize itself from a tracer.
+// Every pass takes variant n of all enums, so values of each variant are seen.
+pub fn trace<T: DeserializeOwned>() -> Shape {
+    let pass = |variant| {
+        let mut shape = Shape::Unknown;
+        let _ = T::deserialize(Tracer {
+            out: &mut shape,
+            variant,
+        });
+        shape
+    };
+    let mut shape = pass(0);
+    for variant in 1..most_variants(&shape) {
+        merge(&mut shape, pass(variant));
+    }
+    shape
+}
+
+fn most_variants(shape: &Shape) -> usize {
+    match shape {
+        Shape::Enum(v) => v
+            .iter()
+            .filter_map(|(_, s)| s.as_ref().map(most_variants))
+            .fold(v.len(), usize::max),
+        Shape::Option(s) | Shape::Seq(s) => most_variants(s),
+        Shape::Struct(f) => f.iter().map(|(_, s)| most_variants(s)).max().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+// Adds variant values found by another pass
+fn merge(shape: &mut Shape, other: Shape) {
+    match (shape, other) {
+        (Shape::Enum(a), Shape::Enum(b)) => {
+            for ((_, x), (_, y)) in a.iter_mut().zip(b) {
+                match (x, y) {
+                    (Some(x), Some(y)) => merge(x, y),
+                    (x @ None, y) => *x = y,
+                    _ => (),
+                }
+            }
+        }
+        (Shape::Option(a), Shape::Option(b)) | (Shape::Seq(a), Shape::Seq(b)) => merge(a, *b),
+        (Shape::Struct(a), Shape::Struct(b)) => {
+            for ((_, x), (_, y)) in a.iter_mut().zip(b) {
+                merge(x, y);
+            }
+        }
+        _ => (),
+    }
+}
+
+struct Tracer<'a> {
+    out: &'a mut Shape,
+    // index of the variant enums take in this pass
+    variant: usize,
+}
+
+macro_rules! trace_int {
+    ($($method:ident $visit:ident $t:ty),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                *self.out = Shape::Int {
+                    min: (<$t>::MIN as i128).max(i64::MIN as i128) as i64,
+                    max: (<$t>::MAX as i128).min(i64::MAX as i128) as i64,
+                };
+                visitor.$visit(0)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Tracer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        *self.out = Shape::Unknown;
+        visitor.visit_unit()
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        *self.out = Shape::Bool;
+        visitor.visit_bool(false)
+    }
+
+    trace_int!(
+        deserialize_i8 visit_i8 i8,
+        deserialize_i16 visit_i16 i16,
+        deserialize_i32 visit_i32 i32,
+        deserialize_i64 visit_i64 i64,
+        deserialize_u8 visit_u8 u8,
+        deserialize_u16 visit_u16 u16,
+        deserialize_u32 visit_u32 u32,
+        deserialize_u64 visit_u64 u64
+    );
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        *self.out = Shape::Float;
+        visitor.visit_f32(0.)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        *self.out = Shape::Float;
+        visitor.visit_f64(0.)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        *self.out = Shape::Str;
+        visitor.visit_str("")
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        *self.out = Shape::Str;
+        visitor.visit_string(String::new())
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        *self.out = Shape::Str;
+        visitor.visit_char(' ')
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let mut inner = Shape::Unknown;
+        let res = visitor.visit_some(Tracer {
+            out: &mut inner,
+            variant: self.variant,
+        });
+        *self.out = Shape::Option(Box::new(inner));
+        res
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let mut inner = Shape::Unknown;
+        let res = visitor.visit_seq(OneItem {
+            out: Some(&mut inner),
+            variant: self.variant,
+        });
+        *self.out = Shape::Seq(Box::new(inner));
+        res
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        *self.out = Shape::Map;
+        visitor.visit_map(Fields {
+            fields: &[],
+            shapes: Vec::new(),
+            current: None,
+            variant: self.variant,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let mut access = Fields {
+            fields,
+            shapes: Vec::new(),
+            current: None,
+            variant: self.variant,
+        };
+        let res = visitor.visit_map(&mut access);
+        *self.out = Shape::Struct(access.shapes);
+        res
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let index = if self.variant < variants.len() {
+            self.variant
+        } else {
+            0
+        };
+        let mut value = None;
+        let res = visitor.visit_enum(PickVariant {
+            name: variants.get(index).copied().unwrap_or_default(),
+            value: &mut value,
+            variant: self.variant,
+        });
+        let mut shape: Vec<_> = variants.iter().map(|v| (*v, None)).collect();
+        if let Some(v) = shape.get_mut(index) {
+            v.1 = value;
+        }
+        *self.out = Shape::Enum(shape);
+        res
+    }
+
+    serde::forward_to_deserialize_any! {
+        bytes byte_buf unit unit_struct newtype_struct tuple tuple_struct
+        identifier ignored_any
+    }
+}
+
+// Sequence of a single traced element
+struct OneItem<'a> {
+    out: Option<&'a mut Shape>,
+    variant: usize,
+}
+
+impl<'de> SeqAccess<'de> for OneItem<'_> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        match self.out.take() {
+            Some(out) => seed
+                .deserialize(Tracer {
+                    out,
+                    variant: self.variant,
+                })
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+// Struct fields, every value is traced
+struct Fields {
+    fields: &'static [&'static str],
+    shapes: Vec<(&'static str, Shape)>,
+    current: Option<&'static str>,
+    variant: usize,
+}
+
+impl<'de> MapAccess<'de> for Fields {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        let field = match self.fields.get(self.shapes.len()) {
+            Some(f) => *f,
+            None => return Ok(None),
+        };
+        self.current = Some(field);
+        seed.deserialize(field.into_deserializer()).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let mut shape = Shape::Unknown;
+        let res = seed.deserialize(Tracer {
+            out: &mut shape,
+            variant: self.variant,
+        });
+        self.shapes
+            .push((self.current.take().unwrap_or_default(), shape));
+        res
+    }
+}
+
+// Variant taken by enum, records shape of its value
+struct PickVariant<'a> {
+    name: &'static str,
+    value: &'a mut Option<Shape>,
+    variant: usize,
+}
+
+impl<'de, 'a> EnumAccess<'de> for PickVariant<'a> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let v = seed.deserialize(self.name.into_deserializer())?;
+        Ok((v, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for PickVariant<'_> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        let mut shape = Shape::Unknown;
+        let res = seed.deserialize(Tracer {
+            out: &mut shape,
+            variant: self.variant,
+        });
+        *self.value = Some(shape);
+        res
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, _visitor: V) -> Result<V::Value, Error> {
+        Err(de::Error::custom("tuple variants are not supported"))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Error> {
+        Err(de::Error::custom("struct variants are not supported"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_derive::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    enum Kind {
+        Plain,
+        Named(String),
+    }
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct Inner {
+        on: bool,
+    }
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct Sample {
+        flag: bool,
+        small: u8,
+        big: i64,
+        ratio: f64,
+        name: String,
+        limit: Option<u32>,
+        tags: Vec<String>,
+        kind: Kind,
+        kinds: Vec<Kind>,
+        inner: Inner,
+        extra: BTreeMap<String, String>,
+    }
+
+    #[test]
+    fn traces_struct_fields() {
+        let kind = Shape::Enum(vec![("Plain", None), ("Named", Some(Shape::Str))]);
+        assert_eq!(
+            trace::<Sample>(),
+            Shape::Struct(vec![
+                ("flag", Shape::Bool),
+                ("small", Shape::Int { min: 0, max: 255 }),
+                (
+                    "big",
+                    Shape::Int {
+                        min: i64::MIN,
+                        max: i64::MAX
+                    }
+                ),
+                ("ratio", Shape::Float),
+                ("name", Shape::Str),
+                (
+                    "limit",
+                    Shape::Option(Box::new(Shape::Int {
+                        min: 0,
+                        max: u32::MAX.into()
+                    }))
+                ),
+                ("tags", Shape::Seq(Box::new(Shape::Str))),
+                ("kind", kind.clone()),
+                ("kinds", Shape::Seq(Box::new(kind))),
+                ("inner", Shape::Struct(vec![("on", Shape::Bool)])),
+                ("extra", Shape::Map),
+            ])
+        );
+    }
+
+    #[test]
+    fn clamps_unsigned_max() {
+        assert_eq!(
+            trace::<u64>(),
+            Shape::Int {
+                min: 0,
+                max: i64::MAX
+            }
+        );
+    }
+
+    #[test]
+    fn traces_config() {
+        let fields = match trace::<crate::config::Config>() {
+            Shape::Struct(f) => f,
+            s => panic!("{:?}", s),
+        };
+        let get = |name: &str| fields.iter().find(|f| f.0 == name).map(|f| f.1.clone());
+        assert_eq!(
+            get("pas_type"),
+            Some(Shape::Enum(vec![("Generate", None), ("FromFile", None)]))
+        );
+        assert_eq!(
+            get("proxy_files"),
+            Some(Shape::Seq(Box::new(Shape::Enum(vec![
+                ("None", None),
+                ("File", Some(Shape::Str))
+            ]))))
+        );
+        assert_eq!(get("website"), Some(Shape::Option(Box::new(Shape::Str))));
+        assert_eq!(get("plugin_settings"), Some(Shape::Map));
+    }
+}
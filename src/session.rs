@@ -0,0 +1,181 @@
+use eframe::epi;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::paths;
+
+// State restored on next launch. Window size and position are kept by eframe itself.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub config: Option<String>,
+    pub plugin: Option<String>,
+    // Last switched to profile
+    pub profile: Option<String>,
+    pub log_open: bool,
+    pub info_open: bool,
+    pub settings_open: bool,
+    pub config_open: bool,
+    pub log_filter: LogFilter,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            config: None,
+            plugin: None,
+            profile: None,
+            log_open: true,
+            info_open: false,
+            settings_open: false,
+            config_open: false,
+            log_filter: LogFilter::default(),
+        }
+    }
+}
+
+impl Session {
+    pub fn layout(&self, window_size: [f32; 2]) -> Layout {
+        Layout {
+            log_open: self.log_open,
+            info_open: self.info_open,
+            settings_open: self.settings_open,
+            config_open: self.config_open,
+            window_size: Some(window_size),
+        }
+    }
+
+    pub fn set_layout(&mut self, layout: &Layout) {
+        self.log_open = layout.log_open;
+        self.info_open = layout.info_open;
+        self.settings_open = layout.settings_open;
+        self.config_open = layout.config_open;
+    }
+}
+
+// Open panels and window size, kept in profiles
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Layout {
+    pub log_open: bool,
+    pub info_open: bool,
+    pub settings_open: bool,
+    pub config_open: bool,
+    pub window_size: Option<[f32; 2]>,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        let s = Session::default();
+        Self {
+            log_open: s.log_open,
+            info_open: s.info_open,
+            settings_open: s.settings_open,
+            config_open: s.config_open,
+            window_size: None,
+        }
+    }
+}
+
+// Only log lines containing text are shown
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogFilter {
+    pub text: String,
+    pub case_sensitive: bool,
+}
+
+impl LogFilter {
+    pub fn apply(&self, log: &str) -> String {
+        if self.text.is_empty() {
+            return log.to_owned();
+        }
+        let needle = if self.case_sensitive {
+            self.text.clone()
+        } else {
+            self.text.to_lowercase()
+        };
+        log.lines()
+            .filter(|l| {
+                if self.case_sensitive {
+                    l.contains(&needle)
+                } else {
+                    l.to_lowercase().contains(&needle)
+                }
+            })
+            .fold(String::new(), |mut out, l| {
+                out.push_str(l);
+                out.push('\n');
+                out
+            })
+    }
+}
+
+// Installs with different data directories keep separate sessions
+fn key() -> String {
+    format!("session:{}", paths::get().root().display())
+}
+
+pub fn load(storage: Option<&dyn epi::Storage>) -> Session {
+    storage
+        .and_then(|s| epi::get_value(s, &key()))
+        .unwrap_or_default()
+}
+
+pub fn store(storage: &mut dyn epi::Storage, session: &Session) {
+    epi::set_value(storage, &key(), session);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use eframe::epi::Storage;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Memory(HashMap<String, String>);
+
+    impl Storage for Memory {
+        fn get_string(&self, key: &str) -> Option<String> {
+            self.0.get(key).cloned()
+        }
+
+        fn set_string(&mut self, key: &str, value: String) {
+            self.0.insert(key.to_owned(), value);
+        }
+
+        fn flush(&mut self) {}
+    }
+
+    #[test]
+    fn restores_stored_session() {
+        let mut storage = Memory::default();
+        assert!(load(Some(&storage)).log_open);
+
+        let session = Session {
+            config: Some("main".to_owned()),
+            profile: Some("work".to_owned()),
+            log_open: false,
+            log_filter: LogFilter {
+                text: "error".to_owned(),
+                case_sensitive: true,
+            },
+            ..Session::default()
+        };
+        store(&mut storage, &session);
+        let restored = load(Some(&storage));
+        assert_eq!(restored.config.as_deref(), Some("main"));
+        assert_eq!(restored.profile.as_deref(), Some("work"));
+        assert!(!restored.log_open);
+        assert_eq!(restored.log_filter.text, "error");
+
+        // fields missing from older sessions take defaults, broken ones are dropped
+        storage.set_string(&key(), "(config: Some(\"old\"))".to_owned());
+        let restored = load(Some(&storage));
+        assert_eq!(restored.config.as_deref(), Some("old"));
+        assert!(restored.log_open);
+        storage.set_string(&key(), "{".to_owned());
+        assert_eq!(load(Some(&storage)).config, None);
+    }
+}
@@ -0,0 +1,32 @@
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{backup, integrity, paths};
+
+// Application settings kept in settings.toml of data directory
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // Plugin repository: directory or index file path, or http:// URL
+    pub repository: String,
+    // Whether plugins without a trusted signature may run
+    pub unsigned_plugins: integrity::Policy,
+}
+
+impl Settings {
+    pub fn load(log: &mut String) -> Settings {
+        let path = paths::get().settings_file();
+        match std::fs::read_to_string(&path) {
+            Ok(data) => toml::from_str(&data).unwrap_or_else(|e| {
+                log.push_str(&format!("{}: {}\n", path.display(), e));
+                Settings::default()
+            }),
+            Err(_) => Settings::default(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let data = toml::to_vec(self).map_err(|e| e.to_string())?;
+        std::fs::create_dir_all(paths::get().root()).map_err(|e| e.to_string())?;
+        backup::write_atomic(&paths::get().settings_file(), &data).map_err(|e| e.to_string())
+    }
+}
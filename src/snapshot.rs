@@ -0,0 +1,145 @@
+use std::path::{Path, PathBuf};
+
+use similar::TextDiff;
+
+use crate::harness::{render_tree, Harness};
+
+// Initial draw() output is always checked against <tests>/draw.snap.
+// Other cases are described by <tests>/<case>.steps files with lines
+// `click <id>` or `input <id> <value>`, result goes to <tests>/<case>.snap.
+// For installed plugins <tests> is the `tests` folder of the plugin.
+const DRAW_CASE: &str = "draw";
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Passed,
+    Updated,
+    Failed(String),
+}
+
+pub struct CaseResult {
+    pub case: String,
+    pub outcome: Outcome,
+}
+
+fn cases(tests: &Path) -> Vec<(String, Option<PathBuf>)> {
+    let mut cases = vec![(DRAW_CASE.to_owned(), None)];
+    let mut steps: Vec<PathBuf> = match tests.read_dir() {
+        Ok(entries) => entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|e| e == "steps"))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    steps.sort();
+    for p in steps {
+        if let Some(stem) = p.file_stem() {
+            cases.push((stem.to_string_lossy().into_owned(), Some(p)));
+        }
+    }
+    cases
+}
+
+// Replays steps file and returns rendered tree
+fn render_case(name: &str, dir: &Path, steps: Option<&Path>) -> Result<String, String> {
+    let mut h = Harness::load(name, dir)?;
+    h.draw()?;
+    if let Some(path) = steps {
+        let src = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        for (n, line) in src.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(3, ' ');
+            let res = match (parts.next(), parts.next(), parts.next()) {
+                (Some("click"), Some(id), None) => h.click(id),
+                (Some("input"), Some(id), value) => h.input(id, value.unwrap_or("")),
+                _ => Err("expected `click <id>` or `input <id> <value>`".to_owned()),
+            };
+            res.map_err(|e| format!("{}:{}: {}", path.display(), n + 1, e))?;
+            h.draw()?;
+        }
+    }
+    Ok(render_tree(&h.draw()?))
+}
+
+fn diff(expected: &str, actual: &str) -> String {
+    TextDiff::from_lines(expected, actual)
+        .unified_diff()
+        .header("snapshot", "current")
+        .to_string()
+}
+
+// Compares every case of plugin with stored snapshot. In update mode snapshots are rewritten.
+pub fn check(name: &str, dir: &Path, tests: &Path, update: bool) -> Vec<CaseResult> {
+    let mut results = Vec::new();
+    for (case, steps) in cases(tests) {
+        let snap = tests.join(format!("{}.snap", case));
+        let outcome = match render_case(name, dir, steps.as_deref()) {
+            Err(e) => Outcome::Failed(e),
+            Ok(actual) => {
+                let expected = std::fs::read_to_string(&snap).ok();
+                if expected.as_deref() == Some(actual.as_str()) {
+                    Outcome::Passed
+                } else if update {
+                    match std::fs::create_dir_all(tests)
+                        .and_then(|_| std::fs::write(&snap, &actual))
+                    {
+                        Ok(_) => Outcome::Updated,
+                        Err(e) => Outcome::Failed(e.to_string()),
+                    }
+                } else {
+                    match expected {
+                        Some(expected) => Outcome::Failed(diff(&expected, &actual)),
+                        None => Outcome::Failed(format!(
+                            "{} is missing, run with --update to create it",
+                            snap.display()
+                        )),
+                    }
+                }
+            }
+        };
+        results.push(CaseResult { case, outcome });
+    }
+    results
+}
+
+// Prints results, returns true if all cases passed
+pub fn report(name: &str, results: &[CaseResult]) -> bool {
+    let mut ok = true;
+    for r in results {
+        match &r.outcome {
+            Outcome::Passed => println!("{}/{}: ok", name, r.case),
+            Outcome::Updated => println!("{}/{}: updated", name, r.case),
+            Outcome::Failed(e) => {
+                ok = false;
+                println!("{}/{}: FAILED\n{}", name, r.case, e);
+            }
+        }
+    }
+    ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Set FLEXAR_UPDATE_SNAPSHOTS=1 to accept new output.
+    // Snapshots of bundled plugins are kept outside of assets/plugins so they aren't shipped.
+    #[test]
+    fn bundled_plugins_match_snapshots() {
+        let update = std::env::var("FLEXAR_UPDATE_SNAPSHOTS").is_ok_and(|v| v == "1");
+        let assets = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets");
+        let mut ok = true;
+        for entry in assets.join("plugins").read_dir().unwrap().flatten() {
+            if entry.path().is_dir() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let tests = assets.join("plugin-tests").join(&name);
+                ok &= report(&name, &check(&name, &entry.path(), &tests, update));
+            }
+        }
+        assert!(ok, "plugin snapshots differ");
+    }
+}
@@ -0,0 +1,361 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{archive, backup, plugin, version::Version};
+
+// Checksums of unpacked files, kept in plugins directory
+const RECORD: &str = "unpacked.toml";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Unpacked {
+    version: String,
+    // Path relative to plugin directory -> SHA-256 of the bundled file
+    files: BTreeMap<String, String>,
+}
+
+pub fn checksum(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileState {
+    // Not present locally
+    New,
+    // Local file equals bundled one
+    Same,
+    // Local file is untouched since last unpack, bundled one differs
+    Update,
+    // Local file was edited and differs from bundled one
+    Modified,
+}
+
+// What to do with a locally modified file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resolve {
+    Overwrite,
+    Keep,
+    // Bundled file is written next to local one with `.new` appended
+    SideBySide,
+}
+
+impl Resolve {
+    pub const ALL: [Resolve; 3] = [Resolve::Overwrite, Resolve::Keep, Resolve::SideBySide];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Resolve::Overwrite => "Overwrite",
+            Resolve::Keep => "Keep",
+            Resolve::SideBySide => "Write .new",
+        }
+    }
+}
+
+pub struct FilePlan {
+    pub path: String,
+    pub state: FileState,
+    pub resolve: Resolve,
+    data: &'static [u8],
+}
+
+pub struct PluginPlan {
+    pub name: String,
+    pub bundled: String,
+    pub installed: Option<String>,
+    // Installed version is newer, plugin is left alone
+    pub skip: bool,
+    pub files: Vec<FilePlan>,
+}
+
+impl PluginPlan {
+    pub fn conflicts(&self) -> impl Iterator<Item = &FilePlan> {
+        self.files.iter().filter(|f| f.state == FileState::Modified)
+    }
+}
+
+fn bundled_files(
+    dir: &'static include_dir::Dir,
+    out: &mut Vec<&'static include_dir::File<'static>>,
+) {
+    out.extend(dir.files());
+    for d in dir.dirs() {
+        bundled_files(d, out);
+    }
+}
+
+fn read_record(root: &Path, log: &mut String) -> BTreeMap<String, Unpacked> {
+    let path = root.join(RECORD);
+    match std::fs::read_to_string(&path) {
+        Ok(data) => toml::from_str(&data).unwrap_or_else(|e| {
+            log.push_str(&format!("{}: {}\n", path.display(), e));
+            BTreeMap::new()
+        }),
+        Err(_) => BTreeMap::new(),
+    }
+}
+
+// Compares bundled plugins with ones unpacked into root
+pub fn plan(root: &Path, log: &mut String) -> Vec<PluginPlan> {
+    let record = read_record(root, log);
+    let mut plans = Vec::new();
+    for dir in plugin::PLUGINS.dirs() {
+        let name = dir.path().to_string_lossy().into_owned();
+        let bundled = dir
+            .get_file(dir.path().join("config.toml"))
+            .and_then(|f| f.contents_utf8())
+            .and_then(|s| s.parse::<toml::Value>().ok())
+            .and_then(|v| v.get("version").and_then(|v| v.as_str()).map(str::to_owned))
+            .unwrap_or_default();
+        let local = root.join(&name);
+        let installed = local
+            .exists()
+            .then(|| plugin::manifest_version(&local).unwrap_or_default());
+        let skip = match (&installed, Version::parse(&bundled)) {
+            (Some(i), Ok(b)) => Version::parse(i).is_ok_and(|i| i > b),
+            _ => false,
+        };
+        let known = record.get(&name);
+
+        let mut files = Vec::new();
+        bundled_files(dir, &mut files);
+        let files = files
+            .into_iter()
+            .map(|f| {
+                let rel = f.path().strip_prefix(dir.path()).unwrap_or(f.path());
+                let path = archive::entry_name(rel);
+                let state = match std::fs::read(local.join(rel)) {
+                    Err(_) => FileState::New,
+                    Ok(d) if d == f.contents() => FileState::Same,
+                    Ok(d) => {
+                        let sum = checksum(&d);
+                        if known.and_then(|k| k.files.get(&path)) == Some(&sum) {
+                            FileState::Update
+                        } else {
+                            FileState::Modified
+                        }
+                    }
+                };
+                FilePlan {
+                    path,
+                    state,
+                    resolve: Resolve::Keep,
+                    data: f.contents(),
+                }
+            })
+            .collect();
+        plans.push(PluginPlan {
+            name,
+            bundled,
+            installed,
+            skip,
+            files,
+        });
+    }
+    plans.sort_by(|a, b| a.name.cmp(&b.name));
+    plans
+}
+
+fn write(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, data)
+}
+
+// Carries out plans. Returns one summary line per plugin.
+pub fn apply(root: &Path, plans: &[PluginPlan], log: &mut String) -> Vec<String> {
+    let mut record = read_record(root, log);
+    let mut summary = Vec::new();
+    for p in plans {
+        if p.skip {
+            summary.push(format!(
+                "{}: installed {} is newer than bundled {}, skipped",
+                p.name,
+                p.installed.as_deref().unwrap_or_default(),
+                p.bundled
+            ));
+            continue;
+        }
+        let dir = root.join(&p.name);
+        let entry = record.entry(p.name.clone()).or_default();
+        let (mut written, mut kept, mut side) = (0, 0, Vec::new());
+        for f in &p.files {
+            let path = dir.join(&f.path);
+            let target = match (f.state, f.resolve) {
+                (FileState::Same, _) => None,
+                (FileState::Modified, Resolve::Keep) => {
+                    kept += 1;
+                    None
+                }
+                (FileState::Modified, Resolve::SideBySide) => {
+                    let mut name = path.clone().into_os_string();
+                    name.push(".new");
+                    side.push(format!("{}.new", f.path));
+                    Some(PathBuf::from(name))
+                }
+                _ => {
+                    written += 1;
+                    Some(path)
+                }
+            };
+            if let Some(target) = target {
+                if let Err(e) = write(&target, f.data) {
+                    log.push_str(&format!("{}: {}\n", target.display(), e));
+                    continue;
+                }
+            }
+            entry.files.insert(f.path.clone(), checksum(f.data));
+        }
+        entry.version = p.bundled.clone();
+
+        let mut line = match &p.installed {
+            None => format!("{}: installed {}", p.name, p.bundled),
+            Some(v) if *v != p.bundled => format!("{}: updated {} -> {}", p.name, v, p.bundled),
+            Some(_) => format!("{}: {}", p.name, p.bundled),
+        };
+        line.push_str(&format!(", {} files written", written));
+        if kept > 0 {
+            line.push_str(&format!(", {} modified kept", kept));
+        }
+        if !side.is_empty() {
+            line.push_str(&format!(", new versions in {}", side.join(", ")));
+        }
+        summary.push(line);
+    }
+    match toml::to_vec(&record) {
+        Ok(data) => {
+            if let Err(e) = backup::write_atomic(&root.join(RECORD), &data) {
+                log.push_str(&format!("{}: {}\n", RECORD, e));
+            }
+        }
+        Err(e) => log.push_str(&format!("{}: {}\n", RECORD, e)),
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(plans: &[PluginPlan], file: &str) -> FileState {
+        let vk = plans.iter().find(|p| p.name == "vk").unwrap();
+        vk.files.iter().find(|f| f.path == file).unwrap().state
+    }
+
+    #[test]
+    fn unpacks_and_records_files() {
+        let root = crate::paths::test_dir("unpack-fresh");
+        let mut log = String::new();
+        let plans = plan(&root, &mut log);
+        assert_eq!(state(&plans, "plugin.lua"), FileState::New);
+        apply(&root, &plans, &mut log);
+        assert!(log.is_empty(), "{}", log);
+
+        let record = read_record(&root, &mut log);
+        let bundled = plugin::PLUGINS
+            .get_file("vk/plugin.lua")
+            .unwrap()
+            .contents();
+        assert_eq!(record["vk"].files["plugin.lua"], checksum(bundled));
+        assert_eq!(record["vk"].version, "0.1.1");
+        let plans = plan(&root, &mut log);
+        assert_eq!(state(&plans, "plugin.lua"), FileState::Same);
+        assert_eq!(plans[0].installed.as_deref(), Some("0.1.1"));
+    }
+
+    #[test]
+    fn tells_untouched_files_from_edited_ones() {
+        let root = crate::paths::test_dir("unpack-states");
+        let mut log = String::new();
+        apply(&root, &plan(&root, &mut log), &mut log);
+
+        // file unpacked by an older version, not edited since
+        let script = root.join("vk/plugin.lua");
+        std::fs::write(&script, "old").unwrap();
+        let mut record = read_record(&root, &mut log);
+        record
+            .get_mut("vk")
+            .unwrap()
+            .files
+            .insert("plugin.lua".to_owned(), checksum(b"old"));
+        std::fs::write(root.join(RECORD), toml::to_vec(&record).unwrap()).unwrap();
+        assert_eq!(
+            state(&plan(&root, &mut log), "plugin.lua"),
+            FileState::Update
+        );
+
+        std::fs::write(&script, "edited").unwrap();
+        assert_eq!(
+            state(&plan(&root, &mut log), "plugin.lua"),
+            FileState::Modified
+        );
+    }
+
+    #[test]
+    fn resolves_modified_files() {
+        let root = crate::paths::test_dir("unpack-resolve");
+        let mut log = String::new();
+        apply(&root, &plan(&root, &mut log), &mut log);
+        let script = root.join("vk/plugin.lua");
+        std::fs::write(&script, "edited").unwrap();
+
+        let mut plans = plan(&root, &mut log);
+        assert_eq!(plans[0].conflicts().count(), 1);
+        apply(&root, &plans, &mut log);
+        assert_eq!(std::fs::read_to_string(&script).unwrap(), "edited");
+        // kept file is still reported as modified next time
+        assert_eq!(
+            state(&plan(&root, &mut log), "plugin.lua"),
+            FileState::Modified
+        );
+
+        for f in &mut plans[0].files {
+            f.resolve = Resolve::SideBySide;
+        }
+        let summary = apply(&root, &plans, &mut log);
+        assert!(summary[0].contains("plugin.lua.new"), "{:?}", summary);
+        assert_eq!(std::fs::read_to_string(&script).unwrap(), "edited");
+        assert_eq!(
+            std::fs::read(root.join("vk/plugin.lua.new")).unwrap(),
+            plugin::PLUGINS
+                .get_file("vk/plugin.lua")
+                .unwrap()
+                .contents()
+        );
+
+        for f in &mut plans[0].files {
+            f.resolve = Resolve::Overwrite;
+        }
+        apply(&root, &plans, &mut log);
+        assert_eq!(state(&plan(&root, &mut log), "plugin.lua"), FileState::Same);
+        assert!(log.is_empty(), "{}", log);
+    }
+
+    #[test]
+    fn skips_newer_installed_plugin() {
+        let root = crate::paths::test_dir("unpack-newer");
+        std::fs::create_dir_all(root.join("vk")).unwrap();
+        let manifest = plugin::PLUGINS
+            .get_file("vk/config.toml")
+            .unwrap()
+            .contents_utf8()
+            .unwrap()
+            .replace("version = \"0.1.1\"", "version = \"9.0.0\"");
+        std::fs::write(root.join("vk/config.toml"), &manifest).unwrap();
+
+        let mut log = String::new();
+        let plans = plan(&root, &mut log);
+        assert!(plans[0].skip);
+        let summary = apply(&root, &plans, &mut log);
+        assert!(summary[0].contains("newer"), "{:?}", summary);
+        assert_eq!(
+            std::fs::read_to_string(root.join("vk/config.toml")).unwrap(),
+            manifest
+        );
+    }
+}
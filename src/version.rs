@@ -0,0 +1,130 @@
+use std::fmt;
+
+// Version in `major.minor.patch` form, missing parts are zero
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl Version {
+    pub fn parse(s: &str) -> Result<Version, String> {
+        let s = s.trim();
+        let mut parts = [0; 3];
+        for (i, part) in s.split('.').enumerate() {
+            if i == parts.len() {
+                return Err(format!("invalid version {}", s));
+            }
+            parts[i] = part.parse().map_err(|_| format!("invalid version {}", s))?;
+        }
+        Ok(Version {
+            major: parts[0],
+            minor: parts[1],
+            patch: parts[2],
+        })
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+// Versions from min up to, not including, max. Written as `>=1.2, <3`,
+// a bare `1.2` means `>=1.2, <2`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range {
+    pub min: Version,
+    pub max: Option<Version>,
+}
+
+impl Range {
+    pub fn parse(s: &str) -> Result<Range, String> {
+        let s = s.trim();
+        if !s.starts_with(['>', '<']) {
+            let min = Version::parse(s)?;
+            let max = Version {
+                major: min.major + 1,
+                minor: 0,
+                patch: 0,
+            };
+            return Ok(Range {
+                min,
+                max: Some(max),
+            });
+        }
+        let mut range = Range {
+            min: Version::parse("0")?,
+            max: None,
+        };
+        for part in s.split(',') {
+            let part = part.trim();
+            if let Some(v) = part.strip_prefix(">=") {
+                range.min = Version::parse(v)?;
+            } else if let Some(v) = part.strip_prefix('<') {
+                range.max = Some(Version::parse(v)?);
+            } else {
+                return Err(format!("invalid version range {}, expected `>=x, <y`", s));
+            }
+        }
+        Ok(range)
+    }
+
+    pub fn contains(&self, v: Version) -> bool {
+        v >= self.min && !matches!(self.max, Some(max) if v >= max)
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, ">={}", self.min)?;
+        if let Some(max) = self.max {
+            write!(f, ", <{}", max)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> Version {
+        Version::parse(s).unwrap()
+    }
+
+    #[test]
+    fn bare_version_allows_same_major() {
+        let range = Range::parse("1.2").unwrap();
+        assert_eq!(range.to_string(), ">=1.2.0, <2.0.0");
+        assert!(!range.contains(v("1.1.9")));
+        assert!(range.contains(v("1.2")));
+        assert!(range.contains(v("1.9.3")));
+        assert!(!range.contains(v("2.0")));
+    }
+
+    #[test]
+    fn checks_bounds() {
+        let range = Range::parse(">=1.1, <3").unwrap();
+        assert!(!range.contains(v("1.0.9")));
+        assert!(range.contains(v("1.1.0")));
+        assert!(range.contains(v("2.99")));
+        assert!(!range.contains(v("3.0.0")));
+
+        let open = Range::parse(">=1.1").unwrap();
+        assert!(open.contains(v("99.0")));
+        assert!(!open.contains(v("1.0")));
+        assert!(Range::parse("<2").unwrap().contains(v("0.1")));
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        for s in [
+            "", "1.x", "1.2.3.4", ">1", "=1.0", ">=1, <=2", ">=1; <2", ">=, <2",
+        ] {
+            assert!(Range::parse(s).is_err(), "{}", s);
+        }
+    }
+}